@@ -0,0 +1,107 @@
+/*!
+ * Measures the lexer and parser on a large generated input
+ *
+ * Run with `cargo run --release --example lexer_bench`. The input is 200,000 forms
+ * (about 12.8 MB and 6.4 million tokens). Each pass is timed separately, and the median of
+ * several runs is reported, along with the size of a collected token buffer.
+ */
+
+extern crate rhodium;
+
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhodium::base::source::SourceText;
+use rhodium::ir::{ElementData, Lexer, OperationIterator, Parser, Token};
+
+const FORMS: usize = 200_000;
+const RUNS: usize = 15;
+
+fn input() -> String {
+    let mut text = String::new();
+    for i in 0..FORMS {
+        text.push_str(&format!(
+            "(define_symbol \"s{}\" (add (add {} 2) (add 3 (add 4 5))))\n",
+            i, i
+        ));
+    }
+    text
+}
+
+/**
+ * Visits every element in an operation, returning the number of elements (including the
+ * operation itself)
+ */
+fn walk(op_iter: &mut OperationIterator) -> usize {
+    let mut count = 1;
+    while let Some(element) = op_iter.next_element() {
+        count += match element.expect("invalid element").data {
+            ElementData::Operation(mut inner) => walk(&mut inner),
+            _ => 1,
+        };
+    }
+    count
+}
+
+fn lex(source: &Rc<SourceText>) -> usize {
+    let mut count = 0;
+    for token in Lexer::new(Rc::clone(source)) {
+        token.expect("invalid token");
+        count += 1;
+    }
+    count
+}
+
+fn parse(source: &Rc<SourceText>) -> usize {
+    let mut parser = Parser::new(Lexer::new(Rc::clone(source)));
+    let mut count = 0;
+    while let Some(element) = parser.next_element() {
+        count += match element.expect("invalid element").data {
+            ElementData::Operation(mut op_iter) => walk(&mut op_iter),
+            _ => 1,
+        };
+    }
+    count
+}
+
+/**
+ * Runs `pass` several times and returns the median time, along with the pass's result
+ */
+fn time<F: Fn() -> usize>(pass: F) -> (Duration, usize) {
+    let mut times = vec![];
+    let mut result = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = pass();
+        times.push(start.elapsed());
+    }
+    times.sort();
+    (times[RUNS / 2], result)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + f64::from(duration.subsec_nanos()) / 1e6
+}
+
+fn main() {
+    let source = Rc::new(SourceText::new(input()).unwrap());
+    println!("input: {} bytes", source.text().len());
+
+    let (lex_time, tokens) = time(|| lex(&source));
+    let token_size = mem::size_of::<Token>();
+    println!("size_of::<Token>(): {} bytes", token_size);
+    println!(
+        "lexing: {} tokens (a {} byte buffer), median {:.1} ms",
+        tokens,
+        tokens * token_size,
+        millis(lex_time)
+    );
+
+    let (parse_time, elements) = time(|| parse(&source));
+    println!(
+        "parsing: {} elements, median {:.1} ms",
+        elements,
+        millis(parse_time)
+    );
+}
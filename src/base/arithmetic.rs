@@ -0,0 +1,389 @@
+/*!
+ * Integer arithmetic operations
+ *
+ * Integers are stored as `i64`s when they fit and promoted to `BigInt`s when they don't, so
+ * results never wrap around. Dividing by zero produces a `DivisionByZero` error, and results
+ * larger than `MAX_INTEGER_BITS` produce an `IntegerOverflow` error.
+ */
+
+use std::cmp::Ordering;
+
+use base::bigint::BigInt;
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::value::{evaluate_values, null_registrar};
+use base::value::{
+    Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/// The largest integer (in bits) that an arithmetic operation may produce
+pub const MAX_INTEGER_BITS: u64 = 1 << 20;
+
+/**
+ * Applies a function to a single integer operand
+ *
+ * `small` handles `i64` operands and returns None if its result doesn't fit in an `i64`, in
+ * which case `big` is used instead.
+ */
+fn integer_unary_op(
+    small: fn(i64) -> Option<i64>,
+    big: fn(&BigInt) -> BigInt,
+    operands: &[Value],
+) -> EvaluationResult<ValueResult> {
+    Total(check_integer_operands(operands, Some(1)).and_then(|()| {
+        let result = match operands[0] {
+            Value::Integer(value) => small(value),
+            _ => None,
+        };
+        match result {
+            Some(value) => Ok(Value::Integer(value)),
+            None => limit_size(big(&to_bigint(&operands[0]))).map_err(ValueError::new),
+        }
+    }))
+}
+
+/**
+ * Applies a function to a pair of integer operands
+ *
+ * This works like `integer_unary_op()`, except that both functions may fail.
+ */
+fn integer_binary_op(
+    small: fn(i64, i64) -> Result<Option<i64>, ValueErrorCause>,
+    big: fn(&BigInt, &BigInt) -> Result<BigInt, ValueErrorCause>,
+    operands: &[Value],
+) -> EvaluationResult<ValueResult> {
+    Total(check_integer_operands(operands, Some(2)).and_then(|()| {
+        let result = match (&operands[0], &operands[1]) {
+            (&Value::Integer(a), &Value::Integer(b)) => small(a, b),
+            _ => Ok(None),
+        };
+        let result = match result {
+            Ok(Some(value)) => Ok(Value::Integer(value)),
+            Ok(None) => {
+                big(&to_bigint(&operands[0]), &to_bigint(&operands[1])).and_then(limit_size)
+            }
+            Err(cause) => Err(cause),
+        };
+        result.map_err(ValueError::new)
+    }))
+}
+
+/**
+ * Checks that the operands are all integers
+ *
+ * If `expected` is given, the number of operands must match it exactly; otherwise, at least one
+ * operand is required.
+ */
+fn check_integer_operands(operands: &[Value], expected: Option<usize>) -> Result<(), ValueError> {
+    let count_ok = match expected {
+        Some(expected) => operands.len() == expected,
+        None => !operands.is_empty(),
+    };
+    if !count_ok {
+        return Err(ValueError::new(
+            ValueErrorCause::WrongNumberOfOperandsForOperation {
+                expected: expected.unwrap_or(1),
+                found: operands.len(),
+            },
+        ));
+    }
+
+    match operands
+        .iter()
+        .position(|operand| operand.kind() != ValueKind::Integer)
+    {
+        Some(index) => {
+            Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index))
+        }
+        None => Ok(()),
+    }
+}
+
+/**
+ * Converts an integer value to a BigInt
+ *
+ * Panics if the value isn't an integer.
+ */
+fn to_bigint(value: &Value) -> BigInt {
+    match *value {
+        Value::Integer(value) => BigInt::from(value),
+        Value::BigInt(ref value) => value.clone(),
+        _ => panic!("value is not an integer"),
+    }
+}
+
+/**
+ * Converts a BigInt result to a value, failing if it is larger than `MAX_INTEGER_BITS`
+ */
+fn limit_size(value: BigInt) -> Result<Value, ValueErrorCause> {
+    if value.bits() > MAX_INTEGER_BITS {
+        return Err(ValueErrorCause::IntegerOverflow);
+    }
+    Ok(Value::from_bigint(value))
+}
+
+/**
+ * Compares two integer values
+ *
+ * Panics if either value isn't an integer.
+ */
+pub fn compare_integers(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (&Value::Integer(a), &Value::Integer(b)) => a.cmp(&b),
+        _ => to_bigint(a).cmp(&to_bigint(b)),
+    }
+}
+
+fn add(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_add(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(|a, b| Ok(a.checked_add(b)), |a, b| Ok(a + b), operands)
+    }
+
+    evaluate_values(do_add, context, args)
+}
+
+fn sub(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_sub(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(|a, b| Ok(a.checked_sub(b)), |a, b| Ok(a - b), operands)
+    }
+
+    evaluate_values(do_sub, context, args)
+}
+
+fn mul(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_mul(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(|a, b| Ok(a.checked_mul(b)), |a, b| Ok(a * b), operands)
+    }
+
+    evaluate_values(do_mul, context, args)
+}
+
+/**
+ * Divides two integers, rounding towards zero
+ */
+fn div(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_div(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(
+            |a, b| match b {
+                0 => Err(ValueErrorCause::DivisionByZero),
+                _ => Ok(a.checked_div(b)),
+            },
+            |a, b| match a.div_rem(b) {
+                Some((quotient, _)) => Ok(quotient),
+                None => Err(ValueErrorCause::DivisionByZero),
+            },
+            operands,
+        )
+    }
+
+    evaluate_values(do_div, context, args)
+}
+
+/**
+ * Returns the remainder of `div` (which has the same sign as the dividend)
+ */
+fn rem(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_rem(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(
+            |a, b| match b {
+                0 => Err(ValueErrorCause::DivisionByZero),
+                _ => Ok(a.checked_rem(b)),
+            },
+            |a, b| match a.div_rem(b) {
+                Some((_, remainder)) => Ok(remainder),
+                None => Err(ValueErrorCause::DivisionByZero),
+            },
+            operands,
+        )
+    }
+
+    evaluate_values(do_rem, context, args)
+}
+
+fn neg(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_neg(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_unary_op(|a| a.checked_neg(), |a| -a.clone(), operands)
+    }
+
+    evaluate_values(do_neg, context, args)
+}
+
+fn abs(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_abs(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_unary_op(|a| a.checked_abs(), |a| a.abs(), operands)
+    }
+
+    evaluate_values(do_abs, context, args)
+}
+
+/**
+ * Raises an integer to a non-negative integer power
+ */
+fn pow(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_pow(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        integer_binary_op(
+            |base, exponent| {
+                if exponent < 0 {
+                    return Err(ValueErrorCause::NegativeExponent);
+                }
+                match base {
+                    // These are the only bases whose powers can't overflow.
+                    0 | 1 => Ok(Some(if exponent == 0 { 1 } else { base })),
+                    -1 => Ok(Some(if exponent % 2 == 0 { 1 } else { -1 })),
+                    _ if exponent > i64::from(u32::MAX) => Ok(None),
+                    _ => Ok(base.checked_pow(exponent as u32)),
+                }
+            },
+            |base, exponent| {
+                if exponent.is_negative() {
+                    return Err(ValueErrorCause::NegativeExponent);
+                }
+                // Only huge exponents get here with a base of 0, 1 or -1.
+                if base.bits() <= 1 {
+                    let (_, parity) = exponent.div_rem(&BigInt::from(2)).unwrap();
+                    return Ok(if parity.is_zero() {
+                        base.abs()
+                    } else {
+                        base.clone()
+                    });
+                }
+                // Any other base has at least two bits, so this bounds the size of the result.
+                match exponent.to_i64() {
+                    Some(exponent)
+                        if (base.bits() - 1).saturating_mul(exponent as u64)
+                            <= MAX_INTEGER_BITS =>
+                    {
+                        Ok(base.pow(exponent as u32))
+                    }
+                    _ => Err(ValueErrorCause::IntegerOverflow),
+                }
+            },
+            operands,
+        )
+    }
+
+    evaluate_values(do_pow, context, args)
+}
+
+fn min(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_min(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(check_integer_operands(operands, None).map(|()| {
+            let min = operands.iter().min_by(|a, b| compare_integers(a, b));
+            min.unwrap().clone()
+        }))
+    }
+
+    evaluate_values(do_min, context, args)
+}
+
+fn max(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_max(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(check_integer_operands(operands, None).map(|()| {
+            let max = operands.iter().max_by(|a, b| compare_integers(a, b));
+            max.unwrap().clone()
+        }))
+    }
+
+    evaluate_values(do_max, context, args)
+}
+
+const UNARY: Signature = Signature::new(Arity::Fixed(1), &[&[ValueKind::Integer]]);
+const BINARY: Signature = Signature::new(Arity::Fixed(2), &[&[ValueKind::Integer]]);
+const VARIADIC: Signature =
+    Signature::new(Arity::Variadic { required: 1 }, &[&[ValueKind::Integer]]);
+
+/// The arithmetic operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    ("add", Operation::new("add", add, null_registrar, BINARY)),
+    ("sub", Operation::new("sub", sub, null_registrar, BINARY)),
+    ("mul", Operation::new("mul", mul, null_registrar, BINARY)),
+    ("div", Operation::new("div", div, null_registrar, BINARY)),
+    ("rem", Operation::new("rem", rem, null_registrar, BINARY)),
+    ("neg", Operation::new("neg", neg, null_registrar, UNARY)),
+    ("abs", Operation::new("abs", abs, null_registrar, UNARY)),
+    ("pow", Operation::new("pow", pow, null_registrar, BINARY)),
+    ("min", Operation::new("min", min, null_registrar, VARIADIC)),
+    ("max", Operation::new("max", max, null_registrar, VARIADIC)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /**
+     * Applies the arithmetic operation with the given name to integer operands
+     */
+    fn evaluate(name: &str, operands: &[i64]) -> ValueResult {
+        let operation = &OPERATIONS.iter().find(|&&(n, _)| n == name).unwrap().1;
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let operands = operands
+            .iter()
+            .map(|&operand| Ok(Value::Integer(operand)))
+            .collect::<Vec<_>>();
+        match operation.evaluate(&context, &operands) {
+            Total(result) => result,
+            _ => panic!("{} didn't finish", name),
+        }
+    }
+
+    fn value(name: &str, operands: &[i64]) -> String {
+        evaluate(name, operands).unwrap().to_string()
+    }
+
+    fn cause(result: ValueResult) -> ValueErrorCause {
+        result.unwrap_err().cause().clone()
+    }
+
+    #[test]
+    fn integers_are_signed() {
+        assert_eq!(value("sub", &[2, 5]), "-3");
+        assert_eq!(value("mul", &[-4, 5]), "-20");
+        assert_eq!(value("div", &[-7, 2]), "-3");
+        assert_eq!(value("rem", &[-7, 2]), "-1");
+        assert_eq!(value("neg", &[4]), "-4");
+        assert_eq!(value("abs", &[-4]), "4");
+        assert_eq!(value("pow", &[-2, 3]), "-8");
+        assert_eq!(value("min", &[3, -1, 2]), "-1");
+        assert_eq!(value("max", &[3, -1, 2]), "3");
+    }
+
+    #[test]
+    fn division_by_zero_fails() {
+        assert!(matches!(
+            cause(evaluate("div", &[1, 0])),
+            ValueErrorCause::DivisionByZero
+        ));
+        assert!(matches!(
+            cause(evaluate("rem", &[1, 0])),
+            ValueErrorCause::DivisionByZero
+        ));
+        assert!(matches!(
+            cause(evaluate("pow", &[2, -1])),
+            ValueErrorCause::NegativeExponent
+        ));
+    }
+
+    #[test]
+    fn overflowing_results_are_promoted() {
+        let expected = "9223372036854775808";
+        assert_eq!(value("add", &[i64::MAX, 1]), expected);
+        assert_eq!(value("neg", &[i64::MIN]), expected);
+        assert_eq!(value("abs", &[i64::MIN]), expected);
+        assert_eq!(value("div", &[i64::MIN, -1]), expected);
+        assert_eq!(value("rem", &[i64::MIN, -1]), "0");
+    }
+
+    #[test]
+    fn huge_results_fail() {
+        assert!(matches!(
+            cause(evaluate("pow", &[2, 1 << 21])),
+            ValueErrorCause::IntegerOverflow
+        ));
+        assert_eq!(value("pow", &[-1, i64::MAX]), "-1");
+    }
+}
@@ -0,0 +1,657 @@
+/*!
+ * Arbitrary-precision signed integers
+ */
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter, LowerHex};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/**
+ * A signed integer of any size
+ *
+ * Values are stored as a sign and a magnitude. Division rounds towards zero, like Rust's integer
+ * division.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    /// Little-endian base-2^32 digits, with no trailing zeros (so zero has no digits)
+    magnitude: Vec<u32>,
+}
+
+const DIGIT_BITS: u32 = 32;
+/// The largest power of 10 that fits in a digit (used when printing in decimal)
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+const DECIMAL_CHUNK_DIGITS: usize = 9;
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt {
+            negative: false,
+            magnitude: vec![],
+        }
+    }
+
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /**
+     * Returns the number of bits in the magnitude
+     */
+    pub fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(top) => {
+                (self.magnitude.len() as u64 - 1) * u64::from(DIGIT_BITS)
+                    + u64::from(DIGIT_BITS - top.leading_zeros())
+            }
+            None => 0,
+        }
+    }
+
+    /**
+     * Converts the value to an `i64` if it is in range
+     */
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &digit| (acc << DIGIT_BITS) | u64::from(digit));
+        if self.negative {
+            if magnitude <= 1 << 63 {
+                Some((magnitude as i64).wrapping_neg())
+            } else {
+                None
+            }
+        } else if magnitude <= i64::MAX as u64 {
+            Some(magnitude as i64)
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Parses an integer
+     *
+     * The text may start with a minus sign and is read as hexadecimal if it has a `0x` prefix
+     * (after the sign) and as decimal otherwise.
+     */
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, text),
+        };
+        let (radix, digits) = if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+            (16, &unsigned[2..])
+        } else {
+            (10, unsigned)
+        };
+        BigInt::parse_radix(digits, radix).map(|value| if negative { -value } else { value })
+    }
+
+    /**
+     * Parses an unsigned sequence of digits in the given radix (from 2 to 36)
+     */
+    pub fn parse_radix(digits: &str, radix: u32) -> Option<BigInt> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut magnitude = vec![];
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            mul_add_small(&mut magnitude, radix, digit);
+        }
+        Some(BigInt::from_parts(false, magnitude))
+    }
+
+    /**
+     * Divides by another integer, returning the quotient and the remainder
+     *
+     * The quotient is rounded towards zero, and the remainder has the sign of the dividend.
+     * Returns None if `divisor` is zero.
+     */
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &divisor.magnitude);
+        Some((
+            BigInt::from_parts(self.negative != divisor.negative, quotient),
+            BigInt::from_parts(self.negative, remainder),
+        ))
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.magnitude.clone())
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> BigInt {
+        let magnitude = if value < 0 {
+            (value as u64).wrapping_neg()
+        } else {
+            value as u64
+        };
+        BigInt::from_parts(
+            value < 0,
+            vec![magnitude as u32, (magnitude >> DIGIT_BITS) as u32],
+        )
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(
+                self.negative,
+                add_magnitude(&self.magnitude, &other.magnitude),
+            );
+        }
+        // The signs differ, so subtract the smaller magnitude from the larger one.
+        match cmp_magnitude(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(
+                other.negative,
+                sub_magnitude(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::from_parts(
+                self.negative,
+                sub_magnitude(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other.clone()
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            mul_magnitude(&self.magnitude, &other.magnitude),
+        )
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        let negative = !self.negative;
+        BigInt::from_parts(negative, self.magnitude)
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let mut chunks = vec![];
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_small(&magnitude, DECIMAL_CHUNK);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+
+        let mut text = String::new();
+        match chunks.pop() {
+            Some(first) => text.push_str(&first.to_string()),
+            None => text.push('0'),
+        }
+        for chunk in chunks.iter().rev() {
+            text.push_str(&format!("{:0width$}", chunk, width = DECIMAL_CHUNK_DIGITS));
+        }
+        formatter.pad_integral(!self.negative, "", &text)
+    }
+}
+
+impl LowerHex for BigInt {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let mut text = String::new();
+        let mut digits = self.magnitude.iter().rev();
+        match digits.next() {
+            Some(top) => text.push_str(&format!("{:x}", top)),
+            None => text.push('0'),
+        }
+        for digit in digits {
+            text.push_str(&format!("{:08x}", digit));
+        }
+        formatter.pad_integral(!self.negative, "0x", &text)
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &digit) in long.iter().enumerate() {
+        let sum = u64::from(digit) + u64::from(*short.get(i).unwrap_or(&0)) + carry;
+        result.push(sum as u32);
+        carry = sum >> DIGIT_BITS;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/**
+ * Subtracts magnitude `b` from magnitude `a`, which must be at least as large
+ */
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in a.iter().enumerate() {
+        let difference = i64::from(digit) - i64::from(*b.get(i).unwrap_or(&0)) - borrow;
+        result.push(difference as u32);
+        borrow = if difference < 0 { 1 } else { 0 };
+    }
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &a_digit) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &b_digit) in b.iter().enumerate() {
+            let product =
+                u64::from(a_digit) * u64::from(b_digit) + u64::from(result[i + j]) + carry;
+            result[i + j] = product as u32;
+            carry = product >> DIGIT_BITS;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    result
+}
+
+/**
+ * Computes `magnitude * factor + addend` in place
+ */
+fn mul_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = u64::from(addend);
+    for digit in magnitude.iter_mut() {
+        let product = u64::from(*digit) * u64::from(factor) + carry;
+        *digit = product as u32;
+        carry = product >> DIGIT_BITS;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+/**
+ * Divides a magnitude by a single nonzero digit
+ */
+fn div_rem_small(magnitude: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; magnitude.len()];
+    let mut remainder = 0u64;
+    for i in (0..magnitude.len()).rev() {
+        let current = (remainder << DIGIT_BITS) | u64::from(magnitude[i]);
+        quotient[i] = (current / u64::from(divisor)) as u32;
+        remainder = current % u64::from(divisor);
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder as u32)
+}
+
+/**
+ * Divides magnitude `u` by nonzero magnitude `v`
+ *
+ * This is Knuth's algorithm D (The Art of Computer Programming, vol. 2, section 4.3.1).
+ */
+fn div_rem_magnitude(u: &[u32], v: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(u, v) == Ordering::Less {
+        return (vec![], u.to_vec());
+    }
+    if v.len() == 1 {
+        let (quotient, remainder) = div_rem_small(u, v[0]);
+        return (quotient, vec![remainder]);
+    }
+
+    // Normalize so that the divisor's top digit has its high bit set.
+    let shift = v[v.len() - 1].leading_zeros();
+    let n = v.len();
+    let m = u.len() - n;
+    let vn = shift_left(v, shift);
+    let mut un = shift_left(u, shift);
+    un.resize(u.len() + 1, 0);
+
+    let base = 1u64 << DIGIT_BITS;
+    let v_top = u64::from(vn[n - 1]);
+    let v_next = u64::from(vn[n - 2]);
+    let mut quotient = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        // Estimate the quotient digit from the top two digits of the remainder.
+        let numerator = (u64::from(un[j + n]) << DIGIT_BITS) | u64::from(un[j + n - 1]);
+        let mut q_hat = numerator / v_top;
+        let mut r_hat = numerator % v_top;
+        while q_hat >= base || q_hat * v_next > ((r_hat << DIGIT_BITS) | u64::from(un[j + n - 2])) {
+            q_hat -= 1;
+            r_hat += v_top;
+            if r_hat >= base {
+                break;
+            }
+        }
+
+        // Multiply and subtract.
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let product = q_hat * u64::from(vn[i]) + carry;
+            carry = product >> DIGIT_BITS;
+            let difference = i64::from(un[i + j]) - borrow - (product & 0xffff_ffff) as i64;
+            un[i + j] = difference as u32;
+            borrow = if difference < 0 { 1 } else { 0 };
+        }
+        let difference = i64::from(un[j + n]) - borrow - carry as i64;
+        un[j + n] = difference as u32;
+
+        // The estimate was one too large, so add the divisor back.
+        if difference < 0 {
+            q_hat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u64::from(un[i + j]) + u64::from(vn[i]) + carry;
+                un[i + j] = sum as u32;
+                carry = sum >> DIGIT_BITS;
+            }
+            un[j + n] = un[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = q_hat as u32;
+    }
+
+    (quotient, shift_right(&un[..n], shift))
+}
+
+/**
+ * Shifts a magnitude left by less than one digit, returning a result one digit longer
+ */
+fn shift_left(digits: &[u32], shift: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(digits.len() + 1);
+    let mut carry = 0u32;
+    for &digit in digits {
+        result.push((digit << shift) | carry);
+        carry = if shift == 0 {
+            0
+        } else {
+            digit >> (DIGIT_BITS - shift)
+        };
+    }
+    result.push(carry);
+    result
+}
+
+/**
+ * Shifts a magnitude right by less than one digit
+ */
+fn shift_right(digits: &[u32], shift: u32) -> Vec<u32> {
+    (0..digits.len())
+        .map(|i| {
+            let high = match digits.get(i + 1) {
+                Some(&next) if shift > 0 => next << (DIGIT_BITS - shift),
+                _ => 0,
+            };
+            (digits[i] >> shift) | high
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::map::Map;
+    use base::value::Value;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    fn from_i128(value: i128) -> BigInt {
+        big(&value.to_string())
+    }
+
+    /**
+     * Returns a deterministic spread of values with one to four 32-bit digits and both signs
+     */
+    fn samples() -> Vec<i128> {
+        let mut values = vec![
+            1,
+            -1,
+            0xffff_ffff,
+            0x1_0000_0000,
+            0x8000_0000_0000_0001,
+            0xffff_ffff_ffff_ffff,
+            -0x1_0000_0000_0000_0000,
+            0x7fff_ffff_0000_0000_ffff_ffff,
+            0x8000_0000_0000_0000_0000_0000,
+        ];
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for i in 0..60 {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let high = i128::from(state);
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            // Keep the values far enough from i128's limits that products of two fit.
+            let value = ((high << 64) | i128::from(state)) >> (2 + i % 4 * 32);
+            values.push(if i % 2 == 0 { value } else { -value });
+        }
+        values
+    }
+
+    #[test]
+    fn div_rem_matches_native_division() {
+        let samples = samples();
+        for &dividend in samples.iter() {
+            for &divisor in samples.iter() {
+                let (quotient, remainder) =
+                    from_i128(dividend).div_rem(&from_i128(divisor)).unwrap();
+                assert_eq!(
+                    (quotient, remainder),
+                    (from_i128(dividend / divisor), from_i128(dividend % divisor)),
+                    "{} / {}",
+                    dividend,
+                    divisor
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn div_rem_by_multi_digit_divisors() {
+        // Operands like these stress the quotient estimate in the long division.
+        let dividend = big("0x7fffffff800000000000000000000000");
+        let divisor = big("0x800000000000000000000001");
+        let (quotient, remainder) = dividend.div_rem(&divisor).unwrap();
+        assert_eq!(&(&quotient * &divisor) + &remainder, dividend);
+        assert!(remainder < divisor);
+
+        let dividend = big("0xffffffffffffffffffffffffffffffffffffffff");
+        let divisor = big("0xffffffffffffffffffffffff");
+        let (quotient, remainder) = dividend.div_rem(&divisor).unwrap();
+        assert_eq!(&(&quotient * &divisor) + &remainder, dividend);
+        assert!(remainder < divisor);
+
+        assert_eq!(
+            big("5").div_rem(&big("0x10000000000000000")).unwrap().0,
+            BigInt::zero()
+        );
+        assert!(big("5").div_rem(&BigInt::zero()).is_none());
+    }
+
+    #[test]
+    fn sign_rules() {
+        let cases = [(7, 2), (-7, 2), (7, -2), (-7, -2)];
+        for &(a, b) in cases.iter() {
+            let (x, y) = (BigInt::from(a), BigInt::from(b));
+            assert_eq!(&x + &y, BigInt::from(a + b));
+            assert_eq!(&x - &y, BigInt::from(a - b));
+            assert_eq!(&x * &y, BigInt::from(a * b));
+            let (quotient, remainder) = x.div_rem(&y).unwrap();
+            assert_eq!(quotient, BigInt::from(a / b));
+            assert_eq!(remainder, BigInt::from(a % b));
+        }
+
+        // Zero is never negative, however it was produced.
+        assert!(!(-BigInt::zero()).is_negative());
+        assert!(!(&big("-5") + &big("5")).is_negative());
+        assert!(!(&big("-5") * &BigInt::zero()).is_negative());
+        assert!(!big("-4").div_rem(&big("2")).unwrap().1.is_negative());
+        assert_eq!(big("-0"), BigInt::zero());
+
+        assert!(big("-0x100000000000000000") < big("-1"));
+        assert!(big("-1") < BigInt::zero());
+        assert!(big("0x100000000000000000") > big("0xffffffffffffffff"));
+        assert_eq!(big("-3").pow(3), big("-27"));
+        assert_eq!(big("-2").pow(64), big("0x10000000000000000"));
+    }
+
+    #[test]
+    fn decimal_round_trips() {
+        let texts = [
+            "0",
+            "1",
+            "-1",
+            "999999999",
+            "1000000000",
+            "-9223372036854775809",
+            "18446744073709551616",
+            "1000000000000000000000000000001",
+            "-340282366920938463463374607431768211456",
+        ];
+        for text in texts.iter() {
+            assert_eq!(big(text).to_string(), *text);
+        }
+        for &value in samples().iter() {
+            assert_eq!(from_i128(value).to_string(), value.to_string());
+        }
+        assert_eq!(format!("{:>6}", BigInt::from(-42)), "   -42");
+        assert_eq!(format!("{:+}", BigInt::from(42)), "+42");
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        for &value in samples().iter() {
+            let expected = if value < 0 {
+                format!("-{:#x}", -value)
+            } else {
+                format!("{:#x}", value)
+            };
+            let text = format!("{:#x}", from_i128(value));
+            assert_eq!(text, expected);
+            assert_eq!(big(&text), from_i128(value));
+        }
+        assert_eq!(format!("{:x}", BigInt::zero()), "0");
+        assert_eq!(big("0XfF"), BigInt::from(255));
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        for text in ["", "-", "0x", "12a", "0xg", "--1", " 1"].iter() {
+            assert!(BigInt::parse(text).is_none(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn values_normalize_to_i64() {
+        let max = BigInt::from(i64::MAX);
+        let min = BigInt::from(i64::MIN);
+        let one = BigInt::from(1);
+
+        match Value::from_bigint(&(&max + &one) - &one) {
+            Value::Integer(value) => assert_eq!(value, i64::MAX),
+            ref value => panic!("not normalized: {:?}", value),
+        }
+        match Value::from_bigint(&(&min - &one) + &one) {
+            Value::Integer(value) => assert_eq!(value, i64::MIN),
+            ref value => panic!("not normalized: {:?}", value),
+        }
+        match Value::from_bigint(&max + &one) {
+            Value::BigInt(_) => {}
+            ref value => panic!("unexpectedly small: {:?}", value),
+        }
+        match Value::from_bigint(&min - &one) {
+            Value::BigInt(_) => {}
+            ref value => panic!("unexpectedly small: {:?}", value),
+        }
+
+        // Map keys rely on equal integers having the same representation.
+        let mut map = Map::new();
+        map.insert(Value::Integer(5), Value::Boolean(true)).unwrap();
+        let key = Value::from_bigint(&(&max + &BigInt::from(5)) - &max);
+        assert!(map.get(&key).is_some());
+    }
+}
@@ -1,21 +1,27 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::mem;
 use std::rc::{Rc, Weak};
 
 use base::expression;
-use base::expression::EvaluationListener;
-use base::symbol::SymbolTable;
-use base::value::{PartialExpression, Value, ValueResult};
+use base::expression::{EvaluationListener, SignatureMismatch};
+use base::source::Span;
+use base::symbol::{similar_names, SymbolEntry, SymbolTable};
+use base::value;
+use base::value::{PartialExpression, Value, ValueError, ValueErrorCause, ValueKind, ValueResult};
 
 #[derive(Debug, Default)]
 pub struct Scope {
     symbols: SymbolTable,
     parent: Weak<RefCell<Scope>>,
     listeners: HashMap<Box<str>, Vec<Weak<PartialExpression>>>,
+    /// The number of function calls that the scope is nested in
+    call_depth: usize,
 }
 
 pub enum LookupResult {
-    Total(Value),
+    Total(ValueResult),
     Pending,
     NotFound,
 }
@@ -26,9 +32,136 @@ impl Scope {
             symbols: SymbolTable::new(),
             parent: Weak::default(),
             listeners: HashMap::new(),
+            call_depth: 0,
         }
     }
 
+    /**
+     * Creates an empty scope whose lookups fall back to `parent` once finalized
+     */
+    pub fn with_parent(parent: &Rc<RefCell<Scope>>) -> Scope {
+        Scope {
+            parent: Rc::downgrade(parent),
+            ..Scope::new()
+        }
+    }
+
+    /**
+     * Creates the scope of a function call that is nested in `call_depth` calls (including
+     * itself)
+     */
+    pub fn for_call(parent: &Rc<RefCell<Scope>>, call_depth: usize) -> Scope {
+        Scope {
+            call_depth,
+            ..Scope::with_parent(parent)
+        }
+    }
+
+    pub fn parent(&self) -> Option<Rc<RefCell<Scope>>> {
+        self.parent.upgrade()
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.call_depth
+    }
+
+    /**
+     * Returns true if this scope or one of its ancestors has already defined a symbol
+     *
+     * Unlike `get_symbol()`, this doesn't wait for the scope to be finalized.
+     */
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.symbols.get(name).is_some()
+            || self
+                .parent
+                .upgrade()
+                .is_some_and(|parent| parent.borrow().is_defined(name))
+    }
+
+    /**
+     * Returns the names of all symbols visible from this scope (including its ancestors')
+     */
+    pub fn symbol_names(&self) -> Vec<String> {
+        let mut names = self
+            .symbols
+            .names()
+            .map(|name| name.to_owned())
+            .collect::<Vec<_>>();
+        if let Some(parent) = self.parent.upgrade() {
+            names.extend(parent.borrow().symbol_names());
+        }
+        names
+    }
+
+    /**
+     * Returns the span of the form that defined a symbol (searching ancestor scopes)
+     */
+    pub fn definition_span(&self, name: &str) -> Option<Span> {
+        match self.symbols.get_entry(name) {
+            Some(entry) => entry.span,
+            None => self
+                .parent
+                .upgrade()
+                .and_then(|parent| parent.borrow().definition_span(name)),
+        }
+    }
+
+    /**
+     * Marks a scope as complete
+     *
+     * Once a scope is finalized, lookups of names that it doesn't define fall through to its
+     * parent (or fail) instead of waiting for a definition. Partial expressions that were
+     * already waiting for such a name are resolved the same way: they move to the parent, or
+     * fail with an `UndefinedSymbol` error, located at their form, if no ancestor can define the
+     * name.
+     */
+    pub fn finalize(scope: &Rc<RefCell<Scope>>) {
+        let listeners = {
+            let mut scope = scope.borrow_mut();
+            scope.symbols.finalize();
+            mem::take(&mut scope.listeners)
+        };
+        // The scope isn't borrowed while listeners run, since they may define further symbols.
+        for (name, listeners) in listeners {
+            let lookup = scope.borrow().get_symbol(&name);
+            match lookup {
+                LookupResult::Total(value) => notify_listeners(&listeners, &value),
+                LookupResult::Pending => {
+                    // The scope is finalized, so these are passed on to its parent.
+                    let mut scope = scope.borrow_mut();
+                    for listener in listeners {
+                        scope.register_listener(&name, listener);
+                    }
+                }
+                LookupResult::NotFound => {
+                    let error = scope.borrow().undefined_symbol_error(&name);
+                    let operands = [Value::String(name.into())];
+                    // Every listener is a `get_symbol` form, so it fails just as it would have
+                    // if the symbol had been missing when the form was evaluated.
+                    for listener in listeners.iter().filter_map(|l| l.upgrade()) {
+                        let error = error
+                            .clone()
+                            .with_operand_index(0)
+                            .with_context(listener.context(), &operands);
+                        listener.on_evaluated(&*listener, Err(error));
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Builds the error for reading a symbol that neither this scope nor its ancestors define
+     */
+    pub fn undefined_symbol_error(&self, name: &str) -> ValueError {
+        let names = self.symbol_names();
+        let suggestions = similar_names(name, names.iter().map(|name| &**name));
+        ValueError::new(ValueErrorCause::UndefinedSymbol {
+            name: name.to_owned(),
+            suggestions,
+        })
+    }
+
     pub fn get_symbol(&self, name: &str) -> LookupResult {
         match self.symbols.get(name) {
             Some(value) => LookupResult::Total(value.clone()),
@@ -48,43 +181,161 @@ impl Scope {
     /**
      * Defines a named symbol
      *
-     * If the name already exists, an error is returned with the value parameter contained inside.
+     * If the name already exists, the existing entry is returned as an error. A symbol may be
+     * defined as an error, in which case readers of the symbol receive the error. `span` is the
+     * location of the defining form.
      *
      * TODO: figure out how we'll represent non-total values.
      */
-    pub fn define_symbol(&mut self, name: &str, value: Value) -> Result<(), Value> {
-        self.symbols.insert(name, value.clone())?;
+    pub fn define_symbol(
+        &mut self,
+        name: &str,
+        value: ValueResult,
+        span: Option<Span>,
+    ) -> Result<(), SymbolEntry> {
+        self.symbols.insert(name, value.clone(), span)?;
         if let Some(listeners) = self.listeners.remove(name) {
-            for listener in listeners.iter().filter_map({ |l| l.upgrade() }) {
-                listener.on_evaluated(&*listener, Ok(value.clone()));
-            }
+            notify_listeners(&listeners, &value);
         }
         Ok(())
     }
 
+    /**
+     * Makes a partial expression wait for a symbol to be defined
+     *
+     * Once the scope is finalized, listeners for names that it doesn't define wait on its
+     * parent instead, where lookups of those names are resolved.
+     */
     pub fn register_listener(&mut self, name: &str, listener: Weak<PartialExpression>) {
+        if self.symbols.finalized() && self.symbols.get(name).is_none() {
+            if let Some(parent) = self.parent.upgrade() {
+                parent.borrow_mut().register_listener(name, listener);
+                return;
+            }
+        }
         self.listeners
             .entry(name.into())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(listener);
     }
 }
 
+fn notify_listeners(listeners: &[Weak<PartialExpression>], value: &ValueResult) {
+    for listener in listeners.iter().filter_map({ |l| l.upgrade() }) {
+        listener.on_evaluated(&*listener, value.clone());
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct EvaluationContext {
     scope: Rc<RefCell<Scope>>,
+    /* The form being evaluated and its operation (used to annotate errors) */
+    form_span: Option<Span>,
+    operation: Option<Cow<'static, str>>,
 }
 
 impl EvaluationContext {
     pub fn new(scope: Rc<RefCell<Scope>>) -> EvaluationContext {
-        EvaluationContext { scope }
+        EvaluationContext {
+            scope,
+            form_span: None,
+            operation: None,
+        }
+    }
+
+    /**
+     * Creates a copy of the context that evaluates in a different scope
+     */
+    pub fn with_scope(&self, scope: Rc<RefCell<Scope>>) -> EvaluationContext {
+        EvaluationContext {
+            scope,
+            form_span: self.form_span,
+            operation: self.operation.clone(),
+        }
+    }
+
+    /**
+     * Creates a copy of the context for evaluating a specific form
+     */
+    pub fn with_form<N: Into<Cow<'static, str>>>(
+        &self,
+        span: Span,
+        operation: N,
+    ) -> EvaluationContext {
+        EvaluationContext {
+            scope: Rc::clone(&self.scope),
+            form_span: Some(span),
+            operation: Some(operation.into()),
+        }
+    }
+
+    pub fn form_span(&self) -> Option<Span> {
+        self.form_span
+    }
+
+    pub fn operation(&self) -> Option<&str> {
+        self.operation.as_deref()
+    }
+
+    /**
+     * Returns the name of the form's operation, for storing in an error
+     */
+    pub fn operation_name(&self) -> Option<Cow<'static, str>> {
+        self.operation.clone()
     }
 
     pub fn scope(&self) -> Rc<RefCell<Scope>> {
         Rc::clone(&self.scope)
     }
+
+    /**
+     * Defines a named symbol in the context's scope
+     *
+     * Unlike `Scope::define_symbol()`, this releases the scope before notifying the symbol's
+     * listeners, so evaluation triggered by the new value may define further symbols. The
+     * symbol's definition is located at the context's form.
+     */
+    pub fn define_symbol(&self, name: &str, value: ValueResult) -> Result<(), SymbolEntry> {
+        let listeners = {
+            let mut scope = self.scope.borrow_mut();
+            scope.symbols.insert(name, value.clone(), self.form_span)?;
+            scope.listeners.remove(name)
+        };
+        if let Some(listeners) = listeners {
+            notify_listeners(&listeners, &value);
+        }
+        Ok(())
+    }
 }
 
 impl expression::EvaluationContext for EvaluationContext {
     type Value = ValueResult;
+
+    fn propagate_errors(&self, operands: &[ValueResult]) -> Option<ValueResult> {
+        value::propagate_errors(self, operands).map(Err)
+    }
+
+    fn invalid_operation(&self) -> ValueResult {
+        Err(ValueError::new(ValueErrorCause::UnspecifiedError).with_context(self, &[]))
+    }
+
+    fn signature_mismatch(
+        &self,
+        mismatch: SignatureMismatch<ValueKind>,
+        operands: &[ValueResult],
+    ) -> ValueResult {
+        let error = match mismatch {
+            SignatureMismatch::WrongNumberOfOperands { expected, found } => {
+                value::wrong_operand_count(expected, found)
+            }
+            SignatureMismatch::WrongOperandKind { index, .. } => {
+                ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index)
+            }
+        };
+        let values = operands
+            .iter()
+            .filter_map(|operand| operand.as_ref().ok().cloned())
+            .collect::<Vec<_>>();
+        Err(error.with_context(self, &values))
+    }
 }
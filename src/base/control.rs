@@ -0,0 +1,115 @@
+/*!
+ * Conditional operations
+ *
+ * These operations are lazy (see `Operation::lazy()`), so the operands that they don't take are
+ * never built: they can't fail, define symbols or keep the form pending. A condition that is
+ * pending keeps the whole form pending without touching any of the operands that follow it.
+ *
+ * Conditions must be booleans. A condition that fails makes the whole form fail.
+ */
+
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::value::{lazy_condition, lazy_error, lazy_operand};
+use base::value::{
+    LazyStep, Operation, Signature, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * Returns the value of the operand that a conditional operation chose
+ */
+fn chosen_operand(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    index: usize,
+) -> LazyStep {
+    match lazy_operand(context, operands, index) {
+        Ok(value) => LazyStep::Total(Ok(value)),
+        Err(step) => step,
+    }
+}
+
+/**
+ * `(if condition then else)`: returns `then` if the condition is true and `else` otherwise
+ */
+fn if_op(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    match lazy_condition(context, operands, 0) {
+        Ok(true) => chosen_operand(context, operands, 1),
+        Ok(false) => chosen_operand(context, operands, 2),
+        Err(step) => step,
+    }
+}
+
+/**
+ * `(cond condition value ... [default])`: returns the value after the first true condition
+ *
+ * Conditions are evaluated in order. If none of them are true, the result is the default (if
+ * one is given) or a `NoConditionMet` error.
+ */
+fn cond(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    for index in (0..operands.len() / 2).map(|pair| pair * 2) {
+        match lazy_condition(context, operands, index) {
+            Ok(true) => return chosen_operand(context, operands, index + 1),
+            Ok(false) => {}
+            Err(step) => return step,
+        }
+    }
+
+    if operands.len() % 2 == 1 {
+        chosen_operand(context, operands, operands.len() - 1)
+    } else {
+        lazy_error(
+            context,
+            operands,
+            ValueError::new(ValueErrorCause::NoConditionMet),
+        )
+    }
+}
+
+/**
+ * `(when condition value)`: returns the value if the condition is true
+ *
+ * Fails with a `NoConditionMet` error if the condition is false.
+ */
+fn when(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    match lazy_condition(context, operands, 0) {
+        Ok(true) => chosen_operand(context, operands, 1),
+        Ok(false) => lazy_error(
+            context,
+            operands,
+            ValueError::new(ValueErrorCause::NoConditionMet),
+        ),
+        Err(step) => step,
+    }
+}
+
+/// The first operand is a condition; the rest can be anything.
+const CONDITION_FIRST: &[&[ValueKind]] = &[&[ValueKind::Boolean], &[]];
+
+/// The conditional operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "if",
+        Operation::lazy(
+            "if",
+            if_op,
+            Signature::new(Arity::Fixed(3), CONDITION_FIRST),
+        ),
+    ),
+    (
+        "cond",
+        Operation::lazy(
+            "cond",
+            cond,
+            Signature::any(Arity::Variadic { required: 2 }),
+        ),
+    ),
+    (
+        "when",
+        Operation::lazy(
+            "when",
+            when,
+            Signature::new(Arity::Fixed(2), CONDITION_FIRST),
+        ),
+    ),
+];
@@ -0,0 +1,343 @@
+/*!
+ * Conversion and type introspection operations
+ *
+ * `to_string` writes a value as IR text that reads back as the same value. Integers and strings
+ * are written as literals (escaping `"` and `\` in strings), booleans as `(true)` or `(false)`,
+ * floats as `parse_float` calls, and lists and maps as `list` and `map` calls. Functions have no
+ * IR syntax, so they're written as their display text, which doesn't read back.
+ */
+
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::value::{check_operand_count, evaluate_values, null_registrar};
+use base::value::{
+    Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * Parses a string operand with the given parser
+ *
+ * Fails with an `InvalidNumber` error if the parser returns None.
+ */
+fn parse_op(
+    parse: fn(&str) -> Option<Value>,
+    kind: ValueKind,
+    operands: &[Value],
+) -> EvaluationResult<ValueResult> {
+    Total(
+        check_operand_count(operands, Arity::Fixed(1)).and_then(|()| match operands[0] {
+            Value::String(ref text) => parse(text).ok_or_else(|| {
+                ValueError::new(ValueErrorCause::InvalidNumber {
+                    text: text.clone(),
+                    kind,
+                })
+                .with_operand_index(0)
+            }),
+            _ => {
+                Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(0))
+            }
+        }),
+    )
+}
+
+/**
+ * Checks whether a single operand has the given kind
+ */
+fn kind_predicate(kind: ValueKind, operands: &[Value]) -> EvaluationResult<ValueResult> {
+    Total(
+        check_operand_count(operands, Arity::Fixed(1))
+            .map(|()| Value::Boolean(operands[0].kind() == kind)),
+    )
+}
+
+/**
+ * Appends the IR text of a value (see the module documentation)
+ */
+fn write_ir(value: &Value, text: &mut String) {
+    match *value {
+        Value::Integer(_) | Value::BigInt(_) | Value::Function(_) => {
+            text.push_str(&value.to_string())
+        }
+        // `Debug` prints the shortest text that `parse_float` reads back as the same value.
+        Value::Float(value) => text.push_str(&format!("(parse_float \"{:?}\")", value)),
+        Value::String(ref value) => {
+            text.push('"');
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    text.push('\\');
+                }
+                text.push(c);
+            }
+            text.push('"');
+        }
+        Value::Boolean(value) => text.push_str(if value { "(true)" } else { "(false)" }),
+        Value::List(ref elements) => {
+            text.push_str("(list");
+            for element in elements.iter() {
+                text.push(' ');
+                write_ir(element, text);
+            }
+            text.push(')');
+        }
+        Value::Map(ref map) => {
+            text.push_str("(map");
+            for (key, value) in map.iter() {
+                text.push(' ');
+                write_ir(key, text);
+                text.push(' ');
+                write_ir(value, text);
+            }
+            text.push(')');
+        }
+    }
+}
+
+fn to_string(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_to_string(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(check_operand_count(operands, Arity::Fixed(1)).map(|()| {
+            let mut text = String::new();
+            write_ir(&operands[0], &mut text);
+            Value::String(text)
+        }))
+    }
+
+    evaluate_values(do_to_string, context, args)
+}
+
+/**
+ * Parses an integer in the same format as an integer literal
+ */
+fn parse_int(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_parse_int(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        parse_op(Value::parse_integer, ValueKind::Integer, operands)
+    }
+
+    evaluate_values(do_parse_int, context, args)
+}
+
+/**
+ * Parses a float, such as `1.5`, `-2e10`, `inf` or `NaN`
+ */
+fn parse_float(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_parse_float(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        parse_op(
+            |text| text.parse().ok().map(Value::Float),
+            ValueKind::Float,
+            operands,
+        )
+    }
+
+    evaluate_values(do_parse_float, context, args)
+}
+
+/**
+ * Returns the name of a value's kind (such as `"integer"`)
+ */
+fn type_of(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_type_of(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1))
+                .map(|()| Value::String(operands[0].kind().to_string())),
+        )
+    }
+
+    evaluate_values(do_type_of, context, args)
+}
+
+fn is_integer(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_integer(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::Integer, operands)
+    }
+
+    evaluate_values(do_is_integer, context, args)
+}
+
+fn is_float(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_float(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::Float, operands)
+    }
+
+    evaluate_values(do_is_float, context, args)
+}
+
+fn is_string(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_string(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::String, operands)
+    }
+
+    evaluate_values(do_is_string, context, args)
+}
+
+fn is_boolean(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_boolean(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::Boolean, operands)
+    }
+
+    evaluate_values(do_is_boolean, context, args)
+}
+
+fn is_list(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_list(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::List, operands)
+    }
+
+    evaluate_values(do_is_list, context, args)
+}
+
+fn is_map(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_map(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::Map, operands)
+    }
+
+    evaluate_values(do_is_map, context, args)
+}
+
+fn is_function(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_function(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        kind_predicate(ValueKind::Function, operands)
+    }
+
+    evaluate_values(do_is_function, context, args)
+}
+
+const UNARY: Signature = Signature::any(Arity::Fixed(1));
+const PARSE: Signature = Signature::new(Arity::Fixed(1), &[&[ValueKind::String]]);
+
+/// The conversion and introspection operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "to_string",
+        Operation::new("to_string", to_string, null_registrar, UNARY),
+    ),
+    (
+        "parse_int",
+        Operation::new("parse_int", parse_int, null_registrar, PARSE),
+    ),
+    (
+        "parse_float",
+        Operation::new("parse_float", parse_float, null_registrar, PARSE),
+    ),
+    (
+        "type_of",
+        Operation::new("type_of", type_of, null_registrar, UNARY),
+    ),
+    (
+        "is_integer",
+        Operation::new("is_integer", is_integer, null_registrar, UNARY),
+    ),
+    (
+        "is_float",
+        Operation::new("is_float", is_float, null_registrar, UNARY),
+    ),
+    (
+        "is_string",
+        Operation::new("is_string", is_string, null_registrar, UNARY),
+    ),
+    (
+        "is_boolean",
+        Operation::new("is_boolean", is_boolean, null_registrar, UNARY),
+    ),
+    (
+        "is_list",
+        Operation::new("is_list", is_list, null_registrar, UNARY),
+    ),
+    (
+        "is_map",
+        Operation::new("is_map", is_map, null_registrar, UNARY),
+    ),
+    (
+        "is_function",
+        Operation::new("is_function", is_function, null_registrar, UNARY),
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /**
+     * Applies the conversion operation with the given name
+     */
+    fn evaluate(name: &str, operands: &[Value]) -> ValueResult {
+        let operation = &OPERATIONS.iter().find(|&&(n, _)| n == name).unwrap().1;
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let operands = operands.iter().cloned().map(Ok).collect::<Vec<_>>();
+        match operation.evaluate(&context, &operands) {
+            Total(result) => result,
+            _ => panic!("{} didn't finish", name),
+        }
+    }
+
+    fn value(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap().to_string()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn to_string_writes_ir() {
+        let ir = |value: Value| match evaluate("to_string", &[value]).unwrap() {
+            Value::String(text) => text,
+            _ => panic!("to_string didn't return a string"),
+        };
+        assert_eq!(ir(Value::Integer(-5)), "-5");
+        assert_eq!(ir(Value::Float(1.0)), "(parse_float \"1.0\")");
+        assert_eq!(ir(Value::Float(f64::NAN)), "(parse_float \"NaN\")");
+        assert_eq!(ir(string("a\"b\\")), "\"a\\\"b\\\\\"");
+        assert_eq!(ir(Value::Boolean(true)), "(true)");
+        assert_eq!(
+            ir(Value::List(Rc::new(vec![Value::Integer(1), string("x")]))),
+            "(list 1 \"x\")"
+        );
+        assert_eq!(ir(Value::List(Rc::new(vec![]))), "(list)");
+    }
+
+    #[test]
+    fn parsing_numbers() {
+        assert_eq!(value("parse_int", &[string("-0x1f")]), "-31");
+        assert_eq!(
+            value("parse_int", &[string("123456789012345678901234567890")]),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(value("parse_float", &[string("1e300")]), "1e300");
+        assert_eq!(value("parse_float", &[string("-inf")]), "-inf");
+        for &(name, text) in &[
+            ("parse_int", "12abc"),
+            ("parse_int", " 1"),
+            ("parse_float", "x"),
+        ] {
+            let error = evaluate(name, &[string(text)]).unwrap_err();
+            assert!(matches!(
+                error.cause(),
+                ValueErrorCause::InvalidNumber { .. }
+            ));
+        }
+        assert!(matches!(
+            evaluate("parse_int", &[Value::Integer(5)])
+                .unwrap_err()
+                .cause(),
+            ValueErrorCause::WrongTypesForOperation
+        ));
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(value("type_of", &[Value::Float(2.0)]), "\"float\"");
+        assert_eq!(
+            value("type_of", &[Value::List(Rc::new(vec![]))]),
+            "\"list\""
+        );
+        assert_eq!(value("is_integer", &[Value::Integer(1)]), "true");
+        assert_eq!(value("is_float", &[Value::Integer(1)]), "false");
+        assert_eq!(value("is_string", &[string("")]), "true");
+        assert_eq!(value("is_boolean", &[Value::Boolean(false)]), "true");
+        assert_eq!(value("is_list", &[string("")]), "false");
+    }
+}
@@ -0,0 +1,351 @@
+use std::cmp;
+use std::fmt;
+use std::fmt::Display;
+
+use base::source::{SourceLocation, SourceRegistry, Span};
+
+/**
+ * How serious a `Diagnostic` is
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Note => CYAN,
+            Severity::Warning => YELLOW,
+            Severity::Error => RED,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        formatter.write_str(name)
+    }
+}
+
+/**
+ * A secondary location attached to a `Diagnostic`
+ */
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/**
+ * A message about a program, suitable for showing to a user
+ *
+ * Every error type in the crate can be converted into a Diagnostic. Locations are stored as
+ * `Span`s, so rendering a Diagnostic requires a `SourceRegistry` that contains its sources.
+ */
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier for the kind of problem (e.g. `R0002`)
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// The location that the diagnostic is primarily about
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+// ANSI escape sequences used when rendering in colour
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: None,
+            message,
+            span: None,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Diagnostic {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Diagnostic {
+        self.labels.push(Label { span, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    /**
+     * Writes a human-readable rendering of the diagnostic, including the affected source lines
+     *
+     * Spans whose sources can't be found in `registry` are omitted. If `color` is true, ANSI
+     * escape sequences are used to highlight the output.
+     */
+    pub fn render<W: fmt::Write, R: SourceRegistry + ?Sized>(
+        &self,
+        out: &mut W,
+        registry: &R,
+        color: bool,
+    ) -> fmt::Result {
+        let paint = |code: &'static str| if color { code } else { "" };
+        let reset = paint(RESET);
+
+        let location = self.span.and_then(|span| registry.resolve(span));
+        let labels = self
+            .labels
+            .iter()
+            .filter_map(|label| {
+                registry
+                    .resolve(label.span)
+                    .map(|location| (location, &label.message))
+            })
+            .collect::<Vec<_>>();
+
+        write!(out, "{}{}", paint(self.severity.color()), self.severity)?;
+        if let Some(code) = self.code {
+            write!(out, "[{}]", code)?;
+        }
+        writeln!(out, "{}: {}{}{}", reset, paint(BOLD), self.message, reset)?;
+
+        let max_line = location
+            .iter()
+            .chain(labels.iter().map(|label| &label.0))
+            .map(|location| location.start_position().line)
+            .max();
+        let gutter = match max_line {
+            Some(line) => line.to_string().len(),
+            None => 0,
+        };
+
+        if let Some(ref location) = location {
+            writeln!(
+                out,
+                "{:width$}{}-->{} {}",
+                "",
+                paint(BLUE),
+                reset,
+                location,
+                width = gutter
+            )?;
+            Diagnostic::render_snippet(
+                out,
+                location,
+                '^',
+                None,
+                gutter,
+                self.severity.color(),
+                color,
+            )?;
+        }
+
+        for &(ref label_location, message) in labels.iter() {
+            if location.is_none() {
+                writeln!(
+                    out,
+                    "{:width$}{}-->{} {}",
+                    "",
+                    paint(BLUE),
+                    reset,
+                    label_location,
+                    width = gutter
+                )?;
+            }
+            Diagnostic::render_snippet(
+                out,
+                label_location,
+                '-',
+                Some(message),
+                gutter,
+                BLUE,
+                color,
+            )?;
+        }
+
+        for note in self.notes.iter() {
+            writeln!(
+                out,
+                "{:width$} {}={} note: {}",
+                "",
+                paint(BLUE),
+                reset,
+                note,
+                width = gutter
+            )?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Renders the first line of a location with the location's span underlined
+     */
+    fn render_snippet<W: fmt::Write>(
+        out: &mut W,
+        location: &SourceLocation,
+        underline: char,
+        message: Option<&str>,
+        gutter: usize,
+        underline_color: &'static str,
+        color: bool,
+    ) -> fmt::Result {
+        let paint = |code: &'static str| if color { code } else { "" };
+        let reset = paint(RESET);
+
+        let start = location.start_position();
+        let end = location.end_position();
+        let line_text = location.source().line(start.line).unwrap_or("");
+        let line_len = line_text.chars().count();
+
+        // Spans that run past the first line are underlined to the end of that line.
+        let end_column = if end.line == start.line {
+            end.column
+        } else {
+            line_len + 1
+        };
+        let underline_len = cmp::max(end_column.saturating_sub(start.column), 1);
+        let underline_text: String = (0..underline_len).map(|_| underline).collect();
+
+        writeln!(
+            out,
+            "{:width$} {}|{}",
+            "",
+            paint(BLUE),
+            reset,
+            width = gutter
+        )?;
+        writeln!(
+            out,
+            "{}{:>width$} |{} {}",
+            paint(BLUE),
+            start.line,
+            reset,
+            line_text,
+            width = gutter
+        )?;
+        write!(
+            out,
+            "{:width$} {}|{} {:indent$}{}{}",
+            "",
+            paint(BLUE),
+            reset,
+            "",
+            paint(underline_color),
+            underline_text,
+            width = gutter,
+            indent = start.column - 1
+        )?;
+        if let Some(message) = message {
+            write!(out, " {}", message)?;
+        }
+        writeln!(out, "{}", reset)
+    }
+
+    /**
+     * Renders the diagnostic to a string (see `render()`)
+     */
+    pub fn render_to_string<R: SourceRegistry + ?Sized>(
+        &self,
+        registry: &R,
+        color: bool,
+    ) -> String {
+        let mut out = String::new();
+        self.render(&mut out, registry, color).unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::source::{SourceId, SourceMap, SourceText};
+
+    fn sources() -> (SourceMap, SourceId) {
+        let mut sources = SourceMap::new();
+        let id = sources.add(SourceText::with_name("a.rh", "(add 1\n  \"x\")".to_owned()).unwrap());
+        (sources, id)
+    }
+
+    #[test]
+    fn renders_snippet_and_notes() {
+        let (sources, id) = sources();
+        let diagnostic = Diagnostic::error("wrong types".to_owned())
+            .with_code("R0002")
+            .with_span(Span::new(id, 9, 3))
+            .with_label(Span::new(id, 1, 3), "in this operation".to_owned())
+            .with_note("add takes numbers".to_owned());
+        assert_eq!(
+            diagnostic.render_to_string(&sources, false),
+            "error[R0002]: wrong types\n \
+             --> a.rh:2:3\n  \
+             |\n\
+             2 |   \"x\")\n  \
+             |   ^^^\n  \
+             |\n\
+             1 | (add 1\n  \
+             |  --- in this operation\n  \
+             = note: add takes numbers\n"
+        );
+    }
+
+    #[test]
+    fn multiline_spans_are_underlined_to_the_end_of_the_line() {
+        let (sources, id) = sources();
+        let diagnostic = Diagnostic::warning("unused".to_owned()).with_span(Span::new(id, 0, 13));
+        assert_eq!(
+            diagnostic.render_to_string(&sources, false),
+            "warning: unused\n \
+             --> a.rh:1:1\n  \
+             |\n\
+             1 | (add 1\n  \
+             | ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn unknown_sources_are_omitted() {
+        let (sources, _) = sources();
+        let diagnostic =
+            Diagnostic::error("lost".to_owned()).with_span(Span::new(SourceId::DETACHED, 0, 1));
+        assert_eq!(
+            diagnostic.render_to_string(&sources, false),
+            "error: lost\n"
+        );
+        assert!(diagnostic
+            .render_to_string(&sources, true)
+            .starts_with("\x1b[1;31merror\x1b[0m: \x1b[1mlost"));
+    }
+}
@@ -0,0 +1,567 @@
+/*!
+ * Long-form explanations of diagnostic codes
+ */
+
+/**
+ * Documents a single diagnostic code
+ */
+#[derive(Debug)]
+pub struct Explanation {
+    /// The stable code (e.g. `R0004`)
+    pub code: &'static str,
+    /// The name of the corresponding error cause or lint (e.g. `MisplacedSymbol`)
+    pub name: &'static str,
+    /// A one-line description
+    pub summary: &'static str,
+    /// A detailed explanation, including examples and suggested fixes
+    pub text: &'static str,
+}
+
+/**
+ * Looks up the explanation for a diagnostic code
+ *
+ * `query` may be either a code (`R0004`, case-insensitive), the name of an error cause
+ * (`MisplacedSymbol`) or the name of a lint (`unused_symbol`).
+ */
+pub fn explain(query: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS.iter().find(|explanation| {
+        explanation.code.eq_ignore_ascii_case(query) || explanation.name == query
+    })
+}
+
+/**
+ * Returns the explanations for every known diagnostic code
+ */
+pub fn explanations() -> &'static [Explanation] {
+    EXPLANATIONS
+}
+
+const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "R0001",
+        name: "Lexical",
+        summary: "unexpected token",
+        text: "\
+The source contains text that doesn't form a valid token.
+
+Valid tokens are parentheses, symbols (such as `add` or `math::add`), decimal integers and
+double-quoted strings. Inside a string, `\\\"` and `\\\\` stand for a double quote and a
+backslash; any other backslash escape is invalid. Anything else stops the lexer. An unterminated
+string also triggers this error:
+
+    (define_symbol \"x 1)
+
+Close the string or remove the stray characters:
+
+    (define_symbol \"x\" 1)
+",
+    },
+    Explanation {
+        code: "R0002",
+        name: "UnclosedParen",
+        summary: "unclosed parenthesis",
+        text: "\
+The source ended while an operation was still open.
+
+    (add 1 (add 2 3)
+
+Add the missing closing parenthesis:
+
+    (add 1 (add 2 3))
+",
+    },
+    Explanation {
+        code: "R0003",
+        name: "ExtraCloseParen",
+        summary: "unmatched closing parenthesis",
+        text: "\
+A closing parenthesis appeared where no operation was open.
+
+    (add 1 2))
+
+Remove the extra parenthesis, or add the opening parenthesis that it was meant to match.
+",
+    },
+    Explanation {
+        code: "R0004",
+        name: "MisplacedSymbol",
+        summary: "symbol outside of operation position",
+        text: "\
+A symbol appeared somewhere other than directly after an opening parenthesis, and it wasn't
+the name of a parameter.
+
+Symbols name operations, so they can only appear at the start of an operation:
+
+    (add x 1)
+
+The exception is the body of a `defop` or `lambda` form, where the parameters can be written
+as bare symbols:
+
+    (defop inc (x) (add x 1))
+
+To refer to a value that was stored with `define_symbol`, look it up by name with
+`get_symbol`:
+
+    (add (get_symbol \"x\") 1)
+",
+    },
+    Explanation {
+        code: "R0005",
+        name: "MissingOperation",
+        summary: "expected an operation name",
+        text: "\
+An opening parenthesis was followed by something other than an operation name.
+
+    (1 2)
+    ()
+
+Every parenthesized form must start with the name of the operation to apply:
+
+    (add 1 2)
+",
+    },
+    Explanation {
+        code: "R0006",
+        name: "UndefinedOperation",
+        summary: "undefined operation",
+        text: "\
+An operation name with a namespace didn't match any operation in the active operation group.
+
+    (math::ad 1 2)
+
+Check the spelling of the name. Operations from a namespace must either be qualified or
+brought into scope with a `use` directive:
+
+    (math::add 1 2)
+
+    (use \"math\")
+    (add 1 2)
+
+An undefined name without a namespace isn't an error, because a `defop` form may define it
+later. Instead, the call stays pending until the symbol with that name is defined.
+",
+    },
+    Explanation {
+        code: "R0007",
+        name: "WrongNumberOfOperands",
+        summary: "wrong number of operands",
+        text: "\
+An operation was given a number of operands that its signature doesn't allow.
+
+    (add 1 2 3)
+
+`add` takes exactly two operands. Split the expression up:
+
+    (add (add 1 2) 3)
+",
+    },
+    Explanation {
+        code: "R0008",
+        name: "WrongOperandType",
+        summary: "wrong operand type",
+        text: "\
+An operand's value has a kind that the operation doesn't accept at that position. This is
+detected before evaluation for operands whose values are already known.
+
+    (add 1 \"2\")
+
+Pass a value of the expected kind:
+
+    (add 1 2)
+",
+    },
+    Explanation {
+        code: "R0009",
+        name: "InvalidDirective",
+        summary: "invalid directive",
+        text: "\
+A directive was given operands that it doesn't understand.
+
+The `use` directive takes a namespace name and, optionally, an alias, both as strings:
+
+    (use \"math\")
+    (use \"math\" \"m\")
+",
+    },
+    Explanation {
+        code: "R0010",
+        name: "TrailingText",
+        summary: "unexpected trailing text",
+        text: "\
+Text was found after the point where the source was expected to end.
+
+Remove the extra text, or parse the source as a sequence of expressions instead of a
+single one.
+",
+    },
+    Explanation {
+        code: "R0011",
+        name: "InvalidLambda",
+        summary: "invalid lambda",
+        text: "\
+A `lambda` form didn't have the expected operands. A lambda takes a parenthesized list of
+parameter names, written as bare symbols, followed by exactly one body:
+
+    (lambda (x y) (add x y))
+
+Every parameter name must be different. A function without parameters has an empty list:
+
+    (lambda () 5)
+",
+    },
+    Explanation {
+        code: "R0012",
+        name: "InvalidDefop",
+        summary: "invalid defop",
+        text: "\
+A `defop` form didn't have the expected shape. A defop takes the name of the new operation,
+a parenthesized list of parameter names and exactly one body, all written as bare symbols:
+
+    (defop square (x) (mul x x))
+
+The name can't have a namespace, and every parameter name must be different. An operation
+without parameters has an empty list, as in `(defop zero () 0)`. `defop` is only allowed at
+the top level, not inside another form.
+",
+    },
+    Explanation {
+        code: "R0013",
+        name: "OperationRedefined",
+        summary: "operation is already defined",
+        text: "\
+A `defop` form tried to define an operation that already exists, either as a built-in
+operation, as a member of a namespace that was brought into scope, or from an earlier
+`defop`.
+
+    (defop add (x y) (sub x y))
+
+Choose a different name for the new operation.
+",
+    },
+    Explanation {
+        code: "R0100",
+        name: "UnspecifiedError",
+        summary: "unspecified error",
+        text: "\
+An operation failed without giving a more specific reason.
+
+This usually indicates an operation that hasn't been updated to report a more descriptive
+error. Check the operation's documentation for the conditions under which it fails.
+",
+    },
+    Explanation {
+        code: "R0101",
+        name: "WrongNumberOfOperandsForOperation",
+        summary: "wrong number of operands",
+        text: "\
+An operation was evaluated with a number of operands that it doesn't support.
+
+This is the evaluation-time counterpart of R0007. It can occur when an operation is
+evaluated directly rather than through the IR reader, which checks operand counts up front.
+",
+    },
+    Explanation {
+        code: "R0102",
+        name: "WrongTypesForOperation",
+        summary: "wrong operand types for operation",
+        text: "\
+An operation received operand values of kinds that it can't handle.
+
+    (define_symbol \"x\" \"one\")
+    (add (get_symbol \"x\") 1)
+
+Unlike R0008, this is detected during evaluation, because the operand's value wasn't
+known when the expression was read. Make sure that the values flowing into the operation
+have the expected kinds.
+",
+    },
+    Explanation {
+        code: "R0103",
+        name: "Multiple",
+        summary: "multiple operands failed",
+        text: "\
+More than one operand of an operation failed to evaluate. For example, if both operands of
+an `add` evaluate to errors, the `add` fails with an aggregate of the two errors.
+
+Each failure is reported separately underneath the aggregate error. Errors can nest, so the
+failures shown are the root causes: the errors that weren't themselves caused by other errors.
+Fix each of them in turn.
+",
+    },
+    Explanation {
+        code: "R0104",
+        name: "SymbolRedefined",
+        summary: "symbol is already defined",
+        text: "\
+A symbol was defined more than once in the same scope.
+
+    (define_symbol \"x\" 1)
+    (define_symbol \"x\" 2)
+
+Symbols can't be reassigned. Pick a different name for the second definition.
+",
+    },
+    Explanation {
+        code: "R0105",
+        name: "UndefinedSymbol",
+        summary: "undefined symbol",
+        text: "\
+A symbol was read that isn't defined in the current scope or any enclosing scope.
+
+This is only reported once the scope has been finalized (which happens at the end of the
+input); until then, reading an undefined symbol waits for it to be defined. Check the spelling
+of the name. If a defined name is similar, it is suggested in the diagnostic.
+",
+    },
+    Explanation {
+        code: "R0106",
+        name: "DivisionByZero",
+        summary: "division by zero",
+        text: "\
+An integer was divided by zero, either with `div` or with `rem`.
+
+    (div 1 0)
+
+Check the divisor before dividing, or use `or_else` to supply a value for this case:
+
+    (or_else (div x y) 0)
+",
+    },
+    Explanation {
+        code: "R0107",
+        name: "IntegerOverflow",
+        summary: "integer overflow",
+        text: "\
+The result of an arithmetic operation is too large to represent.
+
+    (pow 2 10000000)
+
+Integers grow as needed, so ordinary arithmetic never overflows, but results are limited to
+about a million bits to keep runaway computations from exhausting memory. Operations never
+wrap around; they fail with this error instead.
+",
+    },
+    Explanation {
+        code: "R0108",
+        name: "NegativeExponent",
+        summary: "negative exponent",
+        text: "\
+`pow` was given a negative exponent. The result of such a power isn't generally an integer.
+
+    (pow 2 -1)
+",
+    },
+    Explanation {
+        code: "R0109",
+        name: "IncomparableValues",
+        summary: "values of different kinds were ordered",
+        text: "\
+`lt`, `le`, `gt` or `ge` was given values of different kinds. Values can only be ordered
+against values of the same kind, and lists are ordered by their elements, so their elements
+must be comparable too. Maps and functions can't be ordered at all (for NaN, see R0116).
+
+    (lt 1 \"2\")
+
+Equality is defined between any two values, so `eq` and `ne` never produce this error; values of
+different kinds are simply unequal.
+",
+    },
+    Explanation {
+        code: "R0110",
+        name: "NoConditionMet",
+        summary: "no condition was met",
+        text: "\
+A `when` form's condition was false, or none of a `cond` form's conditions were true and it
+had no default value.
+
+    (cond (eq 1 2) \"a\" (eq 1 3) \"b\")
+
+Give `cond` a trailing default operand, or use `if` when both outcomes need a value.
+",
+    },
+    Explanation {
+        code: "R0111",
+        name: "IndexOutOfRange",
+        summary: "index out of range",
+        text: "\
+A list or string was indexed outside of its bounds, or `head` or `tail` was given an empty
+list.
+
+    (nth (list 1 2 3) 3)
+
+Indices start at 0 and can't be negative, and string indices count characters rather than
+bytes. `slice` and `str.substring` accept an end index equal to the length of the sequence, and
+their start index can't be greater than their end index.
+",
+    },
+    Explanation {
+        code: "R0112",
+        name: "MissingKey",
+        summary: "missing key",
+        text: "\
+`get` was asked for a key that isn't in the map.
+
+    (get (map \"a\" 1) \"b\")
+
+Pass a default value as a third operand to `get` to use it when the key is missing, or check
+for the key first with `has_key`.
+",
+    },
+    Explanation {
+        code: "R0113",
+        name: "UnhashableKey",
+        summary: "value can't be used as a map key",
+        text: "\
+A map was given a key that can't be hashed.
+
+    (map (map) 1)
+
+Integers, strings, booleans and lists of those can be map keys, but floats, maps and functions
+can't.
+",
+    },
+    Explanation {
+        code: "R0114",
+        name: "FormatArgumentMismatch",
+        summary: "wrong number of values for format string",
+        text: "\
+`str.format` was given a different number of values than its format string has `{}`
+placeholders.
+
+    (str.format \"{} and {}\" 1)
+
+Give one value per placeholder. To include a literal brace in the output, write `{{` or `}}`.
+",
+    },
+    Explanation {
+        code: "R0115",
+        name: "InvalidNumber",
+        summary: "string is not a valid number",
+        text: "\
+`parse_int` or `parse_float` was given a string that isn't a number of the expected kind.
+
+    (parse_int \"12abc\")
+
+`parse_int` accepts the same text as an integer literal, such as `-42` or `0x1f`. `parse_float`
+also accepts exponents, `inf` and `NaN`. Neither accepts surrounding whitespace, so use
+`str.trim` first if the text might contain any.
+",
+    },
+    Explanation {
+        code: "R0116",
+        name: "UnorderedNaN",
+        summary: "NaN was ordered",
+        text: "\
+`lt`, `le`, `gt` or `ge` was given a float NaN (or a list containing one). NaN isn't less
+than, equal to or greater than any float, including itself, so it has no place in an order.
+
+    (lt (parse_float \"NaN\") (parse_float \"1\"))
+
+Check for NaN with `ne`, which is true when a value is compared to itself only for NaN:
+
+    (ne (get_symbol \"x\") (get_symbol \"x\"))
+",
+    },
+    Explanation {
+        code: "R0117",
+        name: "CallDepthExceeded",
+        summary: "too many nested function calls",
+        text: "\
+A function was called while more nested function calls than the limit allows were in progress.
+This usually means a recursive function doesn't reach its base case, but a correct recursion
+that is simply very deep fails too:
+
+    (defop count (n) (if (eq n 0) 0 (count (sub n 1))))
+    (count 100000)
+
+Check that the recursion reaches its base case. A deep recursion can often be split into
+halves, so that its depth grows with the logarithm of its input instead.
+",
+    },
+    Explanation {
+        code: "R0200",
+        name: "unused_symbol",
+        summary: "symbol is never read",
+        text: "\
+A symbol was defined but nothing in the program reads it.
+
+    (define_symbol \"x\" 1)
+
+Either read the symbol with `get_symbol` or remove the definition. This lint is a warning
+by default; set `unused_symbol` to `allow` to silence it.
+",
+    },
+    Explanation {
+        code: "R0201",
+        name: "unreachable_form",
+        summary: "form can never be evaluated",
+        text: "\
+An operand of a conditional form can never be evaluated because a literal condition rules it
+out.
+
+    (if (true) 1 (div 1 0))
+    (and (false) (get_symbol \"x\"))
+
+`if`, `when`, `cond`, `and` and `or` only evaluate the operands they take, so the operands
+after a `(true)` or `(false)` that decides the form are dead code. Remove them, or replace the
+literal condition with the one that was intended.
+",
+    },
+    Explanation {
+        code: "R0202",
+        name: "shadowed_symbol",
+        summary: "symbol shadows a parent-scope symbol",
+        text: "\
+A symbol was defined with the same name as a symbol from a parent scope. Within the current
+scope, the new definition hides the parent's value, which can be surprising.
+
+Rename the new symbol if the parent's value is still needed.
+",
+    },
+    Explanation {
+        code: "R0203",
+        name: "undefined_symbol",
+        summary: "symbol is never defined",
+        text: "\
+A form reads a symbol that is never defined.
+
+    (add (get_symbol \"y\") 1)
+
+Reading a symbol that doesn't exist yet leaves the form waiting until the symbol is defined.
+If nothing ever defines it, the form never produces a value. Define the symbol or fix the
+spelling of its name.
+",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_codes() {
+        assert_eq!(explain("R0004").unwrap().name, "MisplacedSymbol");
+        assert_eq!(explain("r0106").unwrap().name, "DivisionByZero");
+        assert!(explain("R9999").is_none());
+        assert!(explain("").is_none());
+    }
+
+    #[test]
+    fn looks_up_names() {
+        assert_eq!(explain("UndefinedSymbol").unwrap().code, "R0105");
+        assert_eq!(explain("unused_symbol").unwrap().code, "R0200");
+        assert!(explain("undefinedsymbol").is_none());
+    }
+
+    #[test]
+    fn codes_and_names_are_unique() {
+        for (i, explanation) in explanations().iter().enumerate() {
+            for other in &explanations()[i + 1..] {
+                assert_ne!(explanation.code, other.code);
+                assert_ne!(explanation.name, other.name);
+            }
+            assert!(!explanation.summary.is_empty());
+            assert!(explanation.text.ends_with('\n'));
+        }
+    }
+}
@@ -1,18 +1,67 @@
 use std;
+use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::mem;
 use std::ptr;
 use std::rc::{Rc, Weak};
 
+use base::source::{SourceText, Span};
+
 /**
  * A trait for all values that can be handled by Operation
  */
-pub trait Value: Clone + Debug {}
+pub trait Value: Clone + Debug {
+    /// A classification of values that operation signatures can refer to
+    type Kind: Copy + Debug + PartialEq + 'static;
+
+    /**
+     * Returns the value's kind
+     *
+     * Values that have no meaningful kind (such as errors) should return None; they are not
+     * checked against operation signatures.
+     */
+    fn kind(&self) -> Option<Self::Kind>;
+}
 
 pub trait EvaluationContext: Clone + Debug {
     type Value: Value + 'static;
+
+    /**
+     * Checks an operation's operands for errors before the operation is evaluated
+     *
+     * If this returns a value, the operation isn't evaluated, and the value becomes its result.
+     * Operations that handle errors themselves (see `Operation::handling_errors()`) skip this
+     * check.
+     */
+    fn propagate_errors(&self, _operands: &[Self::Value]) -> Option<Self::Value> {
+        None
+    }
+
+    /**
+     * Returns the result of an operation that broke its own contract (for example, a lazy
+     * operation that asked for an operand that it had already been given)
+     *
+     * This always indicates a bug in the operation rather than in the program being evaluated.
+     */
+    fn invalid_operation(&self) -> Self::Value;
+
+    /**
+     * Returns the result of an operation whose operands don't match its signature
+     *
+     * Readers usually check signatures before building anything, but expressions that are built
+     * directly (or whose operands have only just been evaluated) are checked again before the
+     * operation runs. `operands` are the values that the operation was applied to, if they are
+     * known.
+     */
+    fn signature_mismatch(
+        &self,
+        mismatch: SignatureMismatch<Kind<Self>>,
+        operands: &[Self::Value],
+    ) -> Self::Value;
 }
 
 /**
@@ -29,6 +78,16 @@ pub trait EvaluationListener<C: EvaluationContext>: Debug {
 pub enum OperandList<C: EvaluationContext> {
     Total(Vec<C::Value>),
     Partial(Vec<Expression<C>>, usize),
+    /// The operands of a lazy operation (see `Operation::lazy()`)
+    Lazy {
+        quoted: Vec<QuotedOperand<C>>,
+        /// The values of the operands that have been evaluated so far
+        forced: Vec<Option<C::Value>>,
+        /// The operand that the operation is waiting for
+        pending: Option<Rc<PartialExpression<C>>>,
+    },
+    /// The expression that a lazy operation delegated its result to (see `LazyStep::Delegate`)
+    Delegated(Expression<C>),
 }
 
 impl<C: EvaluationContext> OperandList<C> {
@@ -85,6 +144,12 @@ pub struct PartialExpression<C: EvaluationContext> {
      * Will be set when the PartialExpression is placed in an OperandList
      */
     index: Cell<usize>,
+    /*
+     * A reference to this PartialExpression
+     *
+     * Lazy operations use this to listen to the operands that they build after they're created.
+     */
+    this: RefCell<Weak<PartialExpression<C>>>,
 }
 
 impl<C: EvaluationContext + 'static> PartialExpression<C> {
@@ -96,8 +161,10 @@ impl<C: EvaluationContext + 'static> PartialExpression<C> {
             listener: Cell::new(None),
             operation_listener: Cell::new(None),
             index: Cell::new(0),
+            this: RefCell::new(Weak::new()),
         });
         exp.operation_listener.set(Some(Rc::downgrade(&exp)));
+        *exp.this.borrow_mut() = Rc::downgrade(&exp);
 
         // Make exp listen for changes in its operands.
         {
@@ -115,11 +182,31 @@ impl<C: EvaluationContext + 'static> PartialExpression<C> {
         exp
     }
 
+    /**
+     * Returns the context that the expression's operation is evaluated in
+     */
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+
     fn try_evaluate_self(&self) -> EvaluationResult<C::Value> {
+        if let OperandList::Delegated(ref mut expression) = *self.operands.borrow_mut() {
+            expression.try_evaluate();
+            return match *expression {
+                Expression::Total(ref value) => EvaluationResult::Total(value.clone()),
+                Expression::Partial(_) => EvaluationResult::Pending,
+            };
+        }
+
+        let is_lazy = matches!(*self.operands.borrow(), OperandList::Lazy { .. });
+        if is_lazy {
+            return self.try_evaluate_lazy();
+        }
+
         let mut operands = self.operands.borrow_mut();
 
         let new_operands: Option<Vec<C::Value>> = match *operands {
-            OperandList::Total(_) => None,
+            OperandList::Total(_) | OperandList::Lazy { .. } | OperandList::Delegated(_) => None,
             OperandList::Partial(ref ops, num_partial) => {
                 if num_partial == 0 {
                     Some(
@@ -154,6 +241,45 @@ impl<C: EvaluationContext + 'static> PartialExpression<C> {
         }
     }
 
+    /**
+     * Resumes a lazy operation if it isn't waiting for an operand
+     */
+    fn try_evaluate_lazy(&self) -> EvaluationResult<C::Value> {
+        let mut operands = self.operands.borrow_mut();
+        let result = match *operands {
+            OperandList::Lazy {
+                ref quoted,
+                ref mut forced,
+                ref mut pending,
+            } => {
+                if pending.is_some() {
+                    return EvaluationResult::Pending;
+                }
+                match run_lazy(&self.operation, &self.context, quoted, forced) {
+                    LazyOutcome::Total(value) => return EvaluationResult::Total(value),
+                    LazyOutcome::Waiting(index, operand) => {
+                        self.listen_to(index, &operand);
+                        *pending = Some(operand);
+                        return EvaluationResult::Pending;
+                    }
+                    LazyOutcome::Delegated(result) => result,
+                }
+            }
+            _ => unreachable!(),
+        };
+        self.listen_to(0, &result);
+        *operands = OperandList::Delegated(Expression::Partial(result));
+        EvaluationResult::Pending
+    }
+
+    /**
+     * Makes this expression listen to an operand that was built after it was created
+     */
+    fn listen_to(&self, index: usize, operand: &Rc<PartialExpression<C>>) {
+        operand.index.set(index);
+        operand.listener.set(Some(self.this.borrow().clone()));
+    }
+
     fn on_self_evaluated(&self, value: C::Value) {
         // We know that no one else will be mutating self.EvaluationListener until we
         // call upgrade(), at which point we don't care whether mutation happens.
@@ -166,17 +292,38 @@ impl<C: EvaluationContext + 'static> PartialExpression<C> {
     }
 
     fn on_operand_evaluated(&self, operand: &PartialExpression<C>, value: C::Value) {
-        {
+        // Keeps a lazy operation's finished operand alive until we're done with it
+        let _finished_operand = {
             let mut operand_list = self.operands.borrow_mut();
 
             // Update the operand list.
-            if let OperandList::Partial(ref mut operands, ref mut num_partial) = *operand_list {
-                if let Expression::Partial(_) = operands[operand.index.get()] {
-                    operands[operand.index.get()] = Expression::Total(value);
-                    *num_partial -= 1;
+            match *operand_list {
+                OperandList::Partial(ref mut operands, ref mut num_partial) => {
+                    if let Expression::Partial(_) = operands[operand.index.get()] {
+                        operands[operand.index.get()] = Expression::Total(value);
+                        *num_partial -= 1;
+                    }
+                    None
                 }
+                OperandList::Lazy {
+                    ref mut forced,
+                    ref mut pending,
+                    ..
+                } => {
+                    if forced[operand.index.get()].is_none() {
+                        forced[operand.index.get()] = Some(value);
+                    }
+                    pending.take()
+                }
+                OperandList::Delegated(ref mut expression) => {
+                    match mem::replace(expression, Expression::Total(value)) {
+                        Expression::Partial(partial) => Some(partial),
+                        Expression::Total(_) => None,
+                    }
+                }
+                OperandList::Total(_) => None,
             }
-        }
+        };
 
         if let EvaluationResult::Total(value) = self.try_evaluate_self() {
             self.on_self_evaluated(value)
@@ -252,7 +399,9 @@ impl<C: EvaluationContext + 'static> Expression<C> {
                     );
                     // TODO: reorganize somehow so we don't need the clone above.
                     let listener = Rc::downgrade(&partial);
-                    op.register(context, &listener, &operand_values);
+                    partial
+                        .operation
+                        .register(context, &listener, &operand_values);
                     Expression::Partial(partial)
                 }
             };
@@ -261,6 +410,48 @@ impl<C: EvaluationContext + 'static> Expression<C> {
         Expression::Partial(PartialExpression::new(op, context.clone(), op_list))
     }
 
+    /**
+     * Builds an Expression from a lazy Operation and its unevaluated operands
+     *
+     * Only the operands that the operation asks for are built.
+     */
+    pub fn from_lazy_op(
+        op: Operation<C>,
+        context: &C,
+        quoted: Vec<QuotedOperand<C>>,
+    ) -> Expression<C> {
+        // The operands' kinds aren't known until they are forced, so only their number is checked.
+        if let Err(mismatch) = op.signature().check(&vec![None; quoted.len()]) {
+            return Expression::Total(context.signature_mismatch(mismatch, &[]));
+        }
+        let mut forced = vec![None; quoted.len()];
+        match run_lazy(&op, context, &quoted, &mut forced) {
+            LazyOutcome::Total(value) => Expression::Total(value),
+            LazyOutcome::Waiting(index, operand) => {
+                let partial = PartialExpression::new(
+                    op,
+                    context.clone(),
+                    OperandList::Lazy {
+                        quoted,
+                        forced,
+                        pending: Some(Rc::clone(&operand)),
+                    },
+                );
+                partial.listen_to(index, &operand);
+                Expression::Partial(partial)
+            }
+            LazyOutcome::Delegated(result) => {
+                let partial = PartialExpression::new(
+                    op,
+                    context.clone(),
+                    OperandList::Delegated(Expression::Partial(Rc::clone(&result))),
+                );
+                partial.listen_to(0, &result);
+                Expression::Partial(partial)
+            }
+        }
+    }
+
     /**
      * Builds a total Expression from a value
      */
@@ -288,6 +479,72 @@ pub enum EvaluationResult<V: Value + 'static> {
     Pending,
 }
 
+/**
+ * The outcome of running a lazy operation's evaluator
+ */
+pub enum LazyStep<C: EvaluationContext> {
+    /// The operation has produced its result.
+    Total(C::Value),
+    /// The operand with the given index must be evaluated before the operation can continue.
+    Force(usize),
+    /// The operation's result is the value of the given expression (which may still be pending).
+    Delegate(Expression<C>),
+}
+
+/**
+ * An operand of a lazy operation that hasn't been built yet
+ */
+pub trait Quoted<C: EvaluationContext>: Debug {
+    /**
+     * Builds the operand's expression
+     *
+     * Each operand of a lazy form is built at most once.
+     */
+    fn build(&self) -> Expression<C>;
+}
+
+pub type QuotedOperand<C> = Rc<dyn Quoted<C>>;
+
+/**
+ * Where a lazy operation stopped (see `run_lazy()`)
+ */
+enum LazyOutcome<C: EvaluationContext> {
+    Total(C::Value),
+    /// The operation is waiting for the operand with the given index.
+    Waiting(usize, Rc<PartialExpression<C>>),
+    /// The operation's result is the value of a pending expression.
+    Delegated(Rc<PartialExpression<C>>),
+}
+
+/**
+ * Runs a lazy operation until it produces a value, has to wait for an operand or delegates its
+ * result to a pending expression
+ */
+fn run_lazy<C: EvaluationContext + 'static>(
+    op: &Operation<C>,
+    context: &C,
+    quoted: &[QuotedOperand<C>],
+    forced: &mut [Option<C::Value>],
+) -> LazyOutcome<C> {
+    loop {
+        let index = match op.step(context, forced) {
+            LazyStep::Total(value) => return LazyOutcome::Total(value),
+            LazyStep::Force(index) => index,
+            LazyStep::Delegate(Expression::Total(value)) => return LazyOutcome::Total(value),
+            LazyStep::Delegate(Expression::Partial(result)) => {
+                return LazyOutcome::Delegated(result)
+            }
+        };
+        if forced[index].is_some() {
+            return LazyOutcome::Total(context.invalid_operation());
+        }
+        match quoted[index].build() {
+            Expression::Total(value) => forced[index] = Some(value),
+            Expression::Partial(partial) => return LazyOutcome::Waiting(index, partial),
+        }
+    }
+}
+
 /**
  * A function that uses an EvaluationContext, an array of operands, and an
  * evaluation listener to handle the evaluation of an Operation
@@ -297,12 +554,167 @@ pub type Evaluator<C> =
 
 pub type Registrar<C> = fn(&C, &Weak<PartialExpression<C>>, &[<C as EvaluationContext>::Value]);
 
-// TODO: handle "lazy"/quoted ops?
+/**
+ * A function that handles the evaluation of a lazy Operation
+ *
+ * It receives the values of the operands that have been evaluated so far (with None for the rest)
+ * and either produces a result, asks for another operand or delegates its result to another
+ * expression. It must not ask for an operand that has already been evaluated; if it does, the
+ * form's result is `EvaluationContext::invalid_operation()`.
+ */
+pub type LazyEvaluator<C> = fn(&C, &[Option<<C as EvaluationContext>::Value>]) -> LazyStep<C>;
+
+/// The kind of the values handled in a given EvaluationContext
+pub type Kind<C> = <<C as EvaluationContext>::Value as Value>::Kind;
+
+/**
+ * The number of operands that an Operation accepts
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    /// Exactly the given number of operands
+    Fixed(usize),
+    /// `required` operands followed by up to `optional` more
+    Optional { required: usize, optional: usize },
+    /// At least `required` operands
+    Variadic { required: usize },
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Fixed(n) => count == n,
+            Arity::Optional { required, optional } => {
+                count >= required && count <= required + optional
+            }
+            Arity::Variadic { required } => count >= required,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match *self {
+            Arity::Fixed(n) => write!(formatter, "{}", n),
+            Arity::Optional { required, optional } => {
+                write!(formatter, "{} to {}", required, required + optional)
+            }
+            Arity::Variadic { required } => write!(formatter, "at least {}", required),
+        }
+    }
+}
+
+/**
+ * Describes the operands that an Operation expects
+ *
+ * `operand_kinds` lists the kinds accepted at each operand position; an empty list accepts any
+ * kind. Operands past the end of `operand_kinds` are checked against its last entry, which allows
+ * variadic operations to constrain their trailing operands.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Signature<K: 'static> {
+    arity: Arity,
+    operand_kinds: &'static [&'static [K]],
+}
+
+/**
+ * Describes how a list of operands fails to match a Signature
+ */
+#[derive(Clone, Debug)]
+pub enum SignatureMismatch<K: 'static> {
+    WrongNumberOfOperands {
+        expected: Arity,
+        found: usize,
+    },
+    WrongOperandKind {
+        index: usize,
+        expected: &'static [K],
+        found: K,
+    },
+}
+
+impl<K: Copy + PartialEq + 'static> Signature<K> {
+    pub const fn new(arity: Arity, operand_kinds: &'static [&'static [K]]) -> Signature<K> {
+        Signature {
+            arity,
+            operand_kinds,
+        }
+    }
+
+    /**
+     * Creates a signature that places no constraints on its operands' kinds
+     */
+    pub const fn any(arity: Arity) -> Signature<K> {
+        Signature {
+            arity,
+            operand_kinds: &[],
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    /**
+     * Returns the kinds accepted at the given operand position (or an empty slice if any kind
+     * is accepted)
+     */
+    pub fn expected_kinds(&self, index: usize) -> &'static [K] {
+        match self.operand_kinds.len() {
+            0 => &[],
+            len => self.operand_kinds[cmp::min(index, len - 1)],
+        }
+    }
+
+    /**
+     * Checks a list of operand kinds against the signature
+     *
+     * Operands whose kinds are not yet known should be given as None; they are assumed to match.
+     */
+    pub fn check(&self, kinds: &[Option<K>]) -> Result<(), SignatureMismatch<K>> {
+        if !self.arity.accepts(kinds.len()) {
+            return Err(SignatureMismatch::WrongNumberOfOperands {
+                expected: self.arity,
+                found: kinds.len(),
+            });
+        }
+
+        for (index, kind) in kinds.iter().enumerate() {
+            if let Some(kind) = *kind {
+                let expected = self.expected_kinds(index);
+                if !expected.is_empty() && !expected.contains(&kind) {
+                    return Err(SignatureMismatch::WrongOperandKind {
+                        index,
+                        expected,
+                        found: kind,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * How an Operation computes its result
+ */
+#[derive(Clone)]
+enum Evaluation<C: EvaluationContext> {
+    /// The operation receives the values of all of its operands.
+    Eager(Evaluator<C>, Registrar<C>),
+    /// The operation decides which of its operands to evaluate.
+    Lazy(LazyEvaluator<C>),
+}
+
+impl<C: EvaluationContext> Copy for Evaluation<C> {}
+
 #[derive(Clone)]
 pub struct Operation<C: EvaluationContext> {
-    name: &'static str,
-    evaluator: Evaluator<C>,
-    registrar: Registrar<C>,
+    /// Borrowed for built-in operations, owned for operations defined by a program
+    name: Cow<'static, str>,
+    evaluation: Evaluation<C>,
+    signature: Signature<Kind<C>>,
+    propagates_errors: bool,
 }
 
 impl<C: EvaluationContext> Operation<C> {
@@ -310,16 +722,122 @@ impl<C: EvaluationContext> Operation<C> {
         name: &'static str,
         evaluator: Evaluator<C>,
         registrar: Registrar<C>,
+        signature: Signature<Kind<C>>,
+    ) -> Operation<C> {
+        Operation {
+            name: Cow::Borrowed(name),
+            evaluation: Evaluation::Eager(evaluator, registrar),
+            signature,
+            propagates_errors: true,
+        }
+    }
+
+    /**
+     * Creates a lazy operation
+     *
+     * Rather than waiting for all of its operands, a lazy operation asks for them one at a time
+     * (see `LazyEvaluator`), so operands that it doesn't need are never built or evaluated. Lazy
+     * operations always receive failed operands; it's up to them to propagate errors.
+     */
+    pub const fn lazy(
+        name: &'static str,
+        evaluator: LazyEvaluator<C>,
+        signature: Signature<Kind<C>>,
     ) -> Operation<C> {
         Operation {
-            name,
-            evaluator,
-            registrar,
+            name: Cow::Borrowed(name),
+            evaluation: Evaluation::Lazy(evaluator),
+            signature,
+            propagates_errors: false,
         }
     }
 
+    /**
+     * Turns off automatic error propagation so that the operation receives failed operands
+     */
+    pub const fn handling_errors(mut self) -> Operation<C> {
+        self.propagates_errors = false;
+        self
+    }
+
+    pub fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+
+    /**
+     * Gives the operation a different name, which (unlike the names given to `new()` and
+     * `lazy()`) may be built at run time
+     */
+    pub fn renamed<N: Into<Cow<'static, str>>>(self, name: N) -> Operation<C> {
+        Operation {
+            name: name.into(),
+            ..self
+        }
+    }
+
+    pub fn signature(&self) -> &Signature<Kind<C>> {
+        &self.signature
+    }
+
+    /**
+     * Returns false if the operation handles failed operands itself
+     */
+    pub fn propagates_errors(&self) -> bool {
+        self.propagates_errors
+    }
+
+    /**
+     * Returns true if the operation was created with `Operation::lazy()`
+     */
+    pub fn is_lazy(&self) -> bool {
+        match self.evaluation {
+            Evaluation::Eager(..) => false,
+            Evaluation::Lazy(_) => true,
+        }
+    }
+
+    /**
+     * Evaluates the operation with the values of all of its operands
+     *
+     * If the operands don't match the operation's signature, the result is
+     * `EvaluationContext::signature_mismatch()`. Lazy operations are given every operand at once.
+     * If one asks for another operand or delegates its result to an expression that is still
+     * pending, the result is `EvaluationContext::invalid_operation()`.
+     */
     pub fn evaluate(&self, context: &C, operands: &[C::Value]) -> EvaluationResult<C::Value> {
-        (self.evaluator)(context, operands)
+        let kinds = operands.iter().map(Value::kind).collect::<Vec<_>>();
+        if let Err(mismatch) = self.signature.check(&kinds) {
+            return EvaluationResult::Total(context.signature_mismatch(mismatch, operands));
+        }
+        if self.propagates_errors {
+            if let Some(result) = context.propagate_errors(operands) {
+                return EvaluationResult::Total(result);
+            }
+        }
+        match self.evaluation {
+            Evaluation::Eager(evaluator, _) => evaluator(context, operands),
+            Evaluation::Lazy(_) => {
+                let forced = operands.iter().cloned().map(Some).collect::<Vec<_>>();
+                match self.step(context, &forced) {
+                    LazyStep::Total(value) | LazyStep::Delegate(Expression::Total(value)) => {
+                        EvaluationResult::Total(value)
+                    }
+                    LazyStep::Force(_) | LazyStep::Delegate(Expression::Partial(_)) => {
+                        EvaluationResult::Total(context.invalid_operation())
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Runs a lazy operation's evaluator
+     */
+    fn step(&self, context: &C, forced: &[Option<C::Value>]) -> LazyStep<C> {
+        match self.evaluation {
+            Evaluation::Lazy(evaluator) => evaluator(context, forced),
+            Evaluation::Eager(..) => unreachable!(),
+        }
     }
 
     pub fn register(
@@ -328,30 +846,198 @@ impl<C: EvaluationContext> Operation<C> {
         listener: &Weak<PartialExpression<C>>,
         operands: &[C::Value],
     ) {
-        (self.registrar)(context, listener, operands)
+        if let Evaluation::Eager(_, registrar) = self.evaluation {
+            registrar(context, listener, operands)
+        }
     }
 }
 
-// For some reason I don't understand, #[derive(Copy)] seems to fail silently, so we have
-// to mark this type manually.
-impl<C: EvaluationContext> Copy for Operation<C> {}
-
 impl<C: EvaluationContext> Debug for Operation<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Operation {{ name: {} }}", self.name)
     }
 }
 
+/// The separator between the components of a canonical operation name
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/**
+ * Converts an operation name to its canonical form
+ *
+ * Namespace components may be separated by either `::` or `.`; the canonical form always uses
+ * `::`, so `str.concat` and `str::concat` name the same operation.
+ */
+pub fn canonical_name(name: &str) -> String {
+    name.split(NAMESPACE_SEPARATOR)
+        .flat_map(|part| part.split('.'))
+        .collect::<Vec<_>>()
+        .join(NAMESPACE_SEPARATOR)
+}
+
+/**
+ * A set of named operations
+ *
+ * Operation names are hierarchical (e.g. `math::add`). Namespaces can be brought into scope with
+ * `use_namespace()`, after which their members can be looked up by their unqualified names or
+ * through an alias.
+ */
 pub struct OperationGroup<C: EvaluationContext> {
     map: HashMap<Box<str>, Operation<C>>,
+    /// Namespaces whose members can be referenced without qualification
+    imports: Vec<Box<str>>,
+    /// Maps aliases to the namespaces they stand for
+    aliases: HashMap<Box<str>, Box<str>>,
+    /// Calls that were read before their operations were defined, in the order they were read
+    forward_calls: VecDeque<(Box<str>, Span)>,
+    /// The names that the last source checked with `may_define()` may define
+    definitions: Option<(Rc<SourceText>, HashSet<Box<str>>)>,
 }
 
 impl<C: EvaluationContext> OperationGroup<C> {
-    pub const fn new(map: HashMap<Box<str>, Operation<C>>) -> OperationGroup<C> {
-        OperationGroup { map }
+    pub fn new(map: HashMap<Box<str>, Operation<C>>) -> OperationGroup<C> {
+        OperationGroup {
+            map: map
+                .into_iter()
+                .map(|(name, op)| (canonical_name(&name).into_boxed_str(), op))
+                .collect(),
+            imports: vec![],
+            aliases: HashMap::new(),
+            forward_calls: VecDeque::new(),
+            definitions: None,
+        }
+    }
+
+    /**
+     * Adds an operation to the group
+     *
+     * If an operation with the same name already exists, it is replaced and returned.
+     */
+    pub fn insert(&mut self, name: &str, op: Operation<C>) -> Option<Operation<C>> {
+        self.map.insert(canonical_name(name).into_boxed_str(), op)
+    }
+
+    /**
+     * Adds every operation in `group` to this group under the given namespace
+     */
+    pub fn insert_namespace(&mut self, namespace: &str, group: OperationGroup<C>) {
+        let namespace = canonical_name(namespace);
+        for (name, op) in group.map {
+            self.map.insert(
+                format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name).into_boxed_str(),
+                op,
+            );
+        }
+    }
+
+    /**
+     * Brings a namespace into scope
+     *
+     * Without an alias, the namespace's members become available by their unqualified names.
+     * With an alias, they become available as `alias::name`. Namespaces that are brought into
+     * scope later take precedence over earlier ones.
+     */
+    pub fn use_namespace(&mut self, namespace: &str, alias: Option<&str>) {
+        let namespace = canonical_name(namespace).into_boxed_str();
+        match alias {
+            Some(alias) => {
+                self.aliases
+                    .insert(canonical_name(alias).into_boxed_str(), namespace);
+            }
+            None => self.imports.push(namespace),
+        }
+    }
+
+    /**
+     * Records a call to an operation that isn't defined yet
+     *
+     * A reader that allows operations to be defined after they're called can use this to report
+     * the calls whose operations never are (see `take_forward_call()`).
+     */
+    pub fn add_forward_call(&mut self, name: &str, span: Span) {
+        self.forward_calls.push_back((name.into(), span));
+    }
+
+    /**
+     * Returns true if a source may define an operation with the given name
+     *
+     * `find_definitions` returns every name that the source may define. It's only called when a
+     * different source is checked than last time, so a reader can check each call cheaply.
+     */
+    pub fn may_define<F>(
+        &mut self,
+        source: &Rc<SourceText>,
+        name: &str,
+        find_definitions: F,
+    ) -> bool
+    where
+        F: FnOnce(&Rc<SourceText>) -> HashSet<Box<str>>,
+    {
+        let current = match self.definitions {
+            Some((ref checked, _)) => Rc::ptr_eq(checked, source),
+            None => false,
+        };
+        if !current {
+            self.definitions = Some((Rc::clone(source), find_definitions(source)));
+        }
+        self.definitions
+            .as_ref()
+            .is_some_and(|(_, names)| names.contains(name))
+    }
+
+    /**
+     * Returns the number of recorded calls, for use with `discard_forward_calls()`
+     */
+    pub fn forward_call_count(&self) -> usize {
+        self.forward_calls.len()
+    }
+
+    /**
+     * Forgets the calls recorded after the first `count`
+     */
+    pub fn discard_forward_calls(&mut self, count: usize) {
+        self.forward_calls.truncate(count);
+    }
+
+    /**
+     * Removes and returns the earliest recorded call whose operation still isn't defined
+     *
+     * Calls whose operations have been defined since are discarded along the way.
+     */
+    pub fn take_forward_call(&mut self) -> Option<(Box<str>, Span)> {
+        while let Some((name, span)) = self.forward_calls.pop_front() {
+            if self.get(&name).is_none() {
+                return Some((name, span));
+            }
+        }
+        None
     }
 
     pub fn get(&self, name: &str) -> Option<&Operation<C>> {
-        self.map.get(name)
+        let name = canonical_name(name);
+        if let Some(op) = self.map.get(name.as_str()) {
+            return Some(op);
+        }
+
+        // Try to expand an alias at the front of the name. If several aliases match (such as `a`
+        // and `a::b`), the longest one wins.
+        let expanded = self
+            .aliases
+            .iter()
+            .filter(|&(alias, _)| {
+                name.starts_with(&**alias) && name[alias.len()..].starts_with(NAMESPACE_SEPARATOR)
+            })
+            .filter_map(|(alias, namespace)| {
+                let full_name = format!("{}{}", namespace, &name[alias.len()..]);
+                self.map.get(full_name.as_str()).map(|op| (alias.len(), op))
+            })
+            .max_by_key(|&(length, _)| length);
+        if let Some((_, op)) = expanded {
+            return Some(op);
+        }
+
+        self.imports.iter().rev().find_map(|namespace| {
+            self.map
+                .get(format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name).as_str())
+        })
     }
 }
@@ -0,0 +1,247 @@
+/*!
+ * Function values and the operations that call them
+ *
+ * Functions are created by `lambda` forms (see `ir::expression::LAMBDA_FORM`), which take a
+ * list of parameter names followed by a body, as in `(lambda (x) (add x 1))`. `apply` calls a
+ * function with its remaining operands as the arguments. A `defop` form (see
+ * `ir::expression::DEFOP_FORM`) stores a function in a symbol and registers an operation of the
+ * same name that calls it.
+ *
+ * A function closes over the scope that it was created in. Each call builds the body in a fresh
+ * child of that scope, in which the parameters are defined as the arguments. The call scope is
+ * finalized before the body is built, so any other symbol that the body reads comes from the
+ * function's scope; if that symbol hasn't been defined yet, the call stays pending until it is.
+ *
+ * Calls can be nested at most `MAX_CALL_DEPTH` deep, so that a runaway recursion fails with a
+ * `CallDepthExceeded` error instead of overflowing the stack.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+use base::context::{EvaluationContext, LookupResult, Scope};
+use base::expression::Arity;
+use base::value::{lazy_error, lazy_operand};
+use base::value::{
+    Expression, LazyStep, Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind,
+    ValueResult,
+};
+
+/**
+ * The maximum number of nested function calls
+ *
+ * Each call takes several kilobytes of stack while its body is built, so the limit is chosen to
+ * fit in the 8 MiB stack that a program's main thread gets by default, even without
+ * optimizations. Threads with smaller stacks (such as the 2 MiB default for spawned threads) can
+ * still overflow.
+ */
+pub const MAX_CALL_DEPTH: usize = 500;
+
+/**
+ * The body of a function, which can be built in any scope
+ */
+pub trait FunctionBody: Debug {
+    fn build(&self, scope: &Rc<RefCell<Scope>>) -> Expression;
+}
+
+/**
+ * A function created by a `lambda` form
+ *
+ * A function holds a strong reference to the scope that it was created in, so a function that
+ * is stored in a symbol of that scope (as with `defop`) forms a reference cycle, and neither is
+ * freed until the program ends. Scopes are expected to live as long as the program anyway.
+ */
+pub struct Function {
+    parameters: Vec<String>,
+    body: Rc<dyn FunctionBody>,
+    /// The scope that the function was created in
+    scope: Rc<RefCell<Scope>>,
+}
+
+impl Function {
+    /**
+     * Creates a function
+     *
+     * The parameter names must be distinct.
+     */
+    pub fn new(
+        parameters: Vec<String>,
+        body: Rc<dyn FunctionBody>,
+        scope: Rc<RefCell<Scope>>,
+    ) -> Function {
+        Function {
+            parameters,
+            body,
+            scope,
+        }
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /**
+     * Builds the function's body with its parameters defined as the given arguments
+     *
+     * There must be one argument per parameter. `call_depth` is the number of calls that the
+     * call is nested in, including itself.
+     */
+    pub fn call(&self, arguments: &[Value], call_depth: usize) -> Expression {
+        let scope = Rc::new(RefCell::new(Scope::for_call(&self.scope, call_depth)));
+        for (name, argument) in self.parameters.iter().zip(arguments) {
+            // The parameter names are distinct, so this can't fail.
+            let _ = scope
+                .borrow_mut()
+                .define_symbol(name, Ok(argument.clone()), None);
+        }
+        Scope::finalize(&scope);
+        self.body.build(&scope)
+    }
+}
+
+// The function's scope may contain the function itself, so it can't be printed.
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Function {{ parameters: {:?} }}", self.parameters)
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("<function (")?;
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str(", ")?;
+            }
+            formatter.write_str(parameter)?;
+        }
+        formatter.write_str(")>")
+    }
+}
+
+/**
+ * Forces all of a lazy operation's operands, from left to right
+ */
+fn forced_values(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+) -> Result<Vec<Value>, LazyStep> {
+    let mut values = vec![];
+    for index in 0..operands.len() {
+        values.push(lazy_operand(context, operands, index)?);
+    }
+    Ok(values)
+}
+
+/**
+ * Calls a function from the context of an `apply` or `defop` operation
+ *
+ * Fails if the function has a different number of parameters or the call would be nested too
+ * deeply.
+ */
+fn call(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    function: &Function,
+    arguments: &[Value],
+) -> LazyStep {
+    if function.parameters().len() != arguments.len() {
+        let error = ValueError::new(ValueErrorCause::WrongNumberOfOperandsForOperation {
+            expected: function.parameters().len(),
+            found: arguments.len(),
+        });
+        return lazy_error(context, operands, error);
+    }
+    let call_depth = context.scope().borrow().call_depth() + 1;
+    if call_depth > MAX_CALL_DEPTH {
+        let error = ValueError::new(ValueErrorCause::CallDepthExceeded {
+            limit: MAX_CALL_DEPTH,
+        });
+        return lazy_error(context, operands, error);
+    }
+    LazyStep::Delegate(function.call(arguments, call_depth))
+}
+
+/**
+ * Calls a function with the remaining operands as its arguments
+ *
+ * The operands are evaluated from left to right. The result is the result of the function's body,
+ * so the call stays pending for as long as the body does.
+ */
+fn apply(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    let values = match forced_values(context, operands) {
+        Ok(values) => values,
+        Err(step) => return step,
+    };
+
+    let function = match values[0] {
+        Value::Function(ref function) => Rc::clone(function),
+        _ => {
+            let error =
+                ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(0);
+            return lazy_error(context, operands, error);
+        }
+    };
+    call(context, operands, &function, &values[1..])
+}
+
+/**
+ * Calls the function stored in the symbol with the same name as the operation
+ *
+ * This is the evaluator of the operations that `defop` forms define. The symbol is looked up
+ * when the operation is called, so a parameter with the same name hides the operation.
+ */
+fn call_defined(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    let arguments = match forced_values(context, operands) {
+        Ok(arguments) => arguments,
+        Err(step) => return step,
+    };
+
+    // Operations are always evaluated in a context for their form.
+    let name = context.operation().unwrap();
+    let function = match context.scope().borrow().get_symbol(name) {
+        LookupResult::Total(Ok(value)) => value,
+        LookupResult::Total(Err(error)) => return LazyStep::Total(Err(error)),
+        // `defop` defines the symbol in the top-level scope before any call can be evaluated.
+        LookupResult::Pending | LookupResult::NotFound => {
+            let error = ValueError::new(ValueErrorCause::UndefinedSymbol {
+                name: name.to_owned(),
+                suggestions: vec![],
+            });
+            return lazy_error(context, operands, error);
+        }
+    };
+    match function {
+        Value::Function(ref function) => call(context, operands, function, &arguments),
+        _ => lazy_error(
+            context,
+            operands,
+            ValueError::new(ValueErrorCause::WrongTypesForOperation),
+        ),
+    }
+}
+
+/**
+ * Returns the operation that a `defop` form registers for a function with the given number of
+ * parameters
+ *
+ * The operation's name must be the name of the symbol that holds the function.
+ */
+pub fn defined_operation(name: String, parameters: usize) -> Operation {
+    Operation::lazy("", call_defined, Signature::any(Arity::Fixed(parameters))).renamed(name)
+}
+
+/// The `apply` operation, which the reader also uses for calls to undefined operations
+pub const APPLY_OP: Operation = Operation::lazy(
+    "apply",
+    apply,
+    Signature::new(
+        Arity::Variadic { required: 1 },
+        &[&[ValueKind::Function], &[]],
+    ),
+);
+
+/// The function operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[("apply", APPLY_OP)];
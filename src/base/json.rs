@@ -0,0 +1,225 @@
+/*!
+ * Machine-readable JSON output for diagnostics
+ *
+ * The output is an object of the form
+ *
+ * ```json
+ * {
+ *   "version": 1,
+ *   "diagnostics": [
+ *     {
+ *       "severity": "error",
+ *       "code": "R0002",
+ *       "message": "unclosed parenthesis",
+ *       "location": {
+ *         "file": "main.rh",
+ *         "start": { "byte": 8, "line": 1, "column": 9 },
+ *         "end": { "byte": 8, "line": 1, "column": 9 }
+ *       },
+ *       "labels": [ { "message": "...", "location": { ... } } ],
+ *       "notes": [ "..." ]
+ *     }
+ *   ]
+ * }
+ * ```
+ *
+ * (without the whitespace). `code`, `location` and `file` are null when unknown, as are `line`
+ * and `column` if the location's source isn't in the registry. Byte offsets
+ * are 0-based; lines and columns are 1-based, and columns are counted in chars. The `end`
+ * position points just past the last char of the location.
+ *
+ * `FORMAT_VERSION` is incremented whenever the format changes in a way that could break
+ * existing consumers; adding new fields does not count as such a change.
+ */
+
+use std::fmt::Write;
+
+use base::diagnostic::{Diagnostic, Label};
+use base::source::{LineColumn, SourceRegistry, Span};
+
+/// The version of the JSON diagnostics format
+pub const FORMAT_VERSION: u32 = 1;
+
+/**
+ * Serializes a list of diagnostics as a JSON document
+ *
+ * `registry` is used to find the sources that the diagnostics refer to.
+ */
+pub fn diagnostics_to_json<R: SourceRegistry + ?Sized>(
+    diagnostics: &[Diagnostic],
+    registry: &R,
+) -> String {
+    let mut out = String::new();
+    write!(out, "{{\"version\":{},\"diagnostics\":[", FORMAT_VERSION).unwrap();
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_diagnostic(&mut out, diagnostic, registry);
+    }
+    out.push_str("]}");
+    out
+}
+
+/**
+ * Serializes a single diagnostic as a JSON object
+ */
+pub fn diagnostic_to_json<R: SourceRegistry + ?Sized>(
+    diagnostic: &Diagnostic,
+    registry: &R,
+) -> String {
+    let mut out = String::new();
+    write_diagnostic(&mut out, diagnostic, registry);
+    out
+}
+
+fn write_diagnostic<R: SourceRegistry + ?Sized>(
+    out: &mut String,
+    diagnostic: &Diagnostic,
+    registry: &R,
+) {
+    out.push_str("{\"severity\":");
+    write_string(out, &diagnostic.severity.to_string());
+    out.push_str(",\"code\":");
+    match diagnostic.code {
+        Some(code) => write_string(out, code),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"message\":");
+    write_string(out, &diagnostic.message);
+    out.push_str(",\"location\":");
+    match diagnostic.span {
+        Some(span) => write_location(out, span, registry),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"labels\":[");
+    for (i, label) in diagnostic.labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_label(out, label, registry);
+    }
+    out.push_str("],\"notes\":[");
+    for (i, note) in diagnostic.notes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, note);
+    }
+    out.push_str("]}");
+}
+
+fn write_label<R: SourceRegistry + ?Sized>(out: &mut String, label: &Label, registry: &R) {
+    out.push_str("{\"message\":");
+    write_string(out, &label.message);
+    out.push_str(",\"location\":");
+    write_location(out, label.span, registry);
+    out.push('}');
+}
+
+fn write_location<R: SourceRegistry + ?Sized>(out: &mut String, span: Span, registry: &R) {
+    let source = registry.source(span.source());
+    out.push_str("{\"file\":");
+    match source.and_then(|source| source.name()) {
+        Some(name) => write_string(out, name),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"start\":");
+    write_position(
+        out,
+        span.start(),
+        source.map(|source| source.position(span.start())),
+    );
+    out.push_str(",\"end\":");
+    write_position(
+        out,
+        span.end(),
+        source.map(|source| source.position(span.end())),
+    );
+    out.push('}');
+}
+
+fn write_position(out: &mut String, byte: usize, position: Option<LineColumn>) {
+    match position {
+        Some(position) => write!(
+            out,
+            "{{\"byte\":{},\"line\":{},\"column\":{}}}",
+            byte, position.line, position.column
+        ),
+        None => write!(out, "{{\"byte\":{},\"line\":null,\"column\":null}}", byte),
+    }
+    .unwrap();
+}
+
+/**
+ * Writes a string literal, escaping it as required by JSON
+ */
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::source::{SourceId, SourceMap, SourceText};
+
+    #[test]
+    fn escapes_strings() {
+        let mut out = String::new();
+        write_string(&mut out, "a\"b\\c\nd\re\tf\u{1}g→");
+        assert_eq!(out, "\"a\\\"b\\\\c\\nd\\re\\tf\\u0001g→\"");
+    }
+
+    #[test]
+    fn unknown_fields_are_null() {
+        let diagnostic = Diagnostic::error("lost".to_owned())
+            .with_span(Span::new(SourceId::DETACHED, 2, 3))
+            .with_note("a \"note\"".to_owned());
+        assert_eq!(
+            diagnostics_to_json(&[diagnostic], &SourceMap::new()),
+            "{\"version\":1,\"diagnostics\":[{\"severity\":\"error\",\"code\":null,\
+             \"message\":\"lost\",\"location\":{\"file\":null,\
+             \"start\":{\"byte\":2,\"line\":null,\"column\":null},\
+             \"end\":{\"byte\":5,\"line\":null,\"column\":null}},\
+             \"labels\":[],\"notes\":[\"a \\\"note\\\"\"]}]}"
+        );
+        assert_eq!(
+            diagnostic_to_json(&Diagnostic::warning("w".to_owned()), &SourceMap::new()),
+            "{\"severity\":\"warning\",\"code\":null,\"message\":\"w\",\"location\":null,\
+             \"labels\":[],\"notes\":[]}"
+        );
+    }
+
+    #[test]
+    fn locations_have_positions() {
+        let mut sources = SourceMap::new();
+        let named = sources.add(SourceText::with_name("a.rh", "(add\n  1 x)".to_owned()).unwrap());
+        let unnamed = sources.add(SourceText::new("x".to_owned()).unwrap());
+        let diagnostic = Diagnostic::error("unknown symbol".to_owned())
+            .with_code("R0101")
+            .with_span(Span::new(named, 9, 1))
+            .with_label(Span::new(unnamed, 0, 1), "here".to_owned());
+        assert_eq!(
+            diagnostic_to_json(&diagnostic, &sources),
+            "{\"severity\":\"error\",\"code\":\"R0101\",\"message\":\"unknown symbol\",\
+             \"location\":{\"file\":\"a.rh\",\
+             \"start\":{\"byte\":9,\"line\":2,\"column\":5},\
+             \"end\":{\"byte\":10,\"line\":2,\"column\":6}},\
+             \"labels\":[{\"message\":\"here\",\"location\":{\"file\":null,\
+             \"start\":{\"byte\":0,\"line\":1,\"column\":1},\
+             \"end\":{\"byte\":1,\"line\":1,\"column\":2}}}],\"notes\":[]}"
+        );
+    }
+}
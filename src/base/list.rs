@@ -0,0 +1,406 @@
+/*!
+ * List operations
+ *
+ * Lists are immutable and share their elements, so copying a list is cheap. Operations that
+ * "modify" a list return a new one.
+ *
+ * Indices start at 0. Negative indices and indices past the end of a list produce an
+ * `IndexOutOfRange` error.
+ */
+
+use std::rc::Rc;
+
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::logic::values_equal;
+use base::value::{check_operand_count, evaluate_values, null_registrar};
+use base::value::{
+    Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * Returns the elements of a list operand
+ */
+fn list_operand(operands: &[Value], index: usize) -> Result<&Rc<Vec<Value>>, ValueError> {
+    match operands[index] {
+        Value::List(ref elements) => Ok(elements),
+        _ => {
+            Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index))
+        }
+    }
+}
+
+/**
+ * Returns the value of an index operand
+ *
+ * The index must be less than `bound`; `length` is the length of the sequence being indexed.
+ */
+pub fn index_operand(
+    operands: &[Value],
+    index: usize,
+    bound: usize,
+    length: usize,
+) -> Result<usize, ValueError> {
+    let out_of_range = || {
+        ValueError::new(ValueErrorCause::IndexOutOfRange {
+            index: operands[index].clone(),
+            length,
+        })
+        .with_operand_index(index)
+    };
+    match operands[index] {
+        Value::Integer(value) if value >= 0 && (value as u64) < bound as u64 => Ok(value as usize),
+        Value::Integer(_) | Value::BigInt(_) => Err(out_of_range()),
+        _ => {
+            Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index))
+        }
+    }
+}
+
+fn list(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_list(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(Ok(Value::List(Rc::new(operands.to_vec()))))
+    }
+
+    evaluate_values(do_list, context, args)
+}
+
+fn len(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_len(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1))
+                .and_then(|()| list_operand(operands, 0))
+                .map(|elements| Value::Integer(elements.len() as i64)),
+        )
+    }
+
+    evaluate_values(do_len, context, args)
+}
+
+/**
+ * Returns the element at an index
+ */
+fn nth(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_nth(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2)).and_then(|()| {
+                let elements = list_operand(operands, 0)?;
+                let index = index_operand(operands, 1, elements.len(), elements.len())?;
+                Ok(elements[index].clone())
+            }),
+        )
+    }
+
+    evaluate_values(do_nth, context, args)
+}
+
+/**
+ * Returns the first element of a non-empty list
+ */
+fn head(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_head(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1)).and_then(|()| {
+                match list_operand(operands, 0)?.first() {
+                    Some(element) => Ok(element.clone()),
+                    None => Err(empty_list()),
+                }
+            }),
+        )
+    }
+
+    evaluate_values(do_head, context, args)
+}
+
+/**
+ * Returns all but the first element of a non-empty list
+ */
+fn tail(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_tail(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1)).and_then(|()| {
+                match list_operand(operands, 0)?.split_first() {
+                    Some((_, rest)) => Ok(Value::List(Rc::new(rest.to_vec()))),
+                    None => Err(empty_list()),
+                }
+            }),
+        )
+    }
+
+    evaluate_values(do_tail, context, args)
+}
+
+/**
+ * The error for taking the head or tail of an empty list
+ */
+fn empty_list() -> ValueError {
+    ValueError::new(ValueErrorCause::IndexOutOfRange {
+        index: Value::Integer(0),
+        length: 0,
+    })
+    .with_operand_index(0)
+}
+
+/**
+ * Prepends a value to a list
+ */
+fn cons(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_cons(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2)).and_then(|()| {
+                let elements = list_operand(operands, 1)?;
+                let mut result = Vec::with_capacity(elements.len() + 1);
+                result.push(operands[0].clone());
+                result.extend(elements.iter().cloned());
+                Ok(Value::List(Rc::new(result)))
+            }),
+        )
+    }
+
+    evaluate_values(do_cons, context, args)
+}
+
+/**
+ * Joins any number of lists
+ */
+fn concat(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_concat(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        let mut result = vec![];
+        for index in 0..operands.len() {
+            match list_operand(operands, index) {
+                Ok(elements) => result.extend(elements.iter().cloned()),
+                Err(error) => return Total(Err(error)),
+            }
+        }
+        Total(Ok(Value::List(Rc::new(result))))
+    }
+
+    evaluate_values(do_concat, context, args)
+}
+
+/**
+ * Returns the elements from a start index up to (but not including) an end index
+ */
+fn slice(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_slice(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(3)).and_then(|()| {
+                let elements = list_operand(operands, 0)?;
+                let length = elements.len();
+                let end = index_operand(operands, 2, length + 1, length)?;
+                let start = index_operand(operands, 1, end + 1, length)?;
+                Ok(Value::List(Rc::new(elements[start..end].to_vec())))
+            }),
+        )
+    }
+
+    evaluate_values(do_slice, context, args)
+}
+
+fn reverse(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_reverse(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1))
+                .and_then(|()| list_operand(operands, 0))
+                .map(|elements| Value::List(Rc::new(elements.iter().rev().cloned().collect()))),
+        )
+    }
+
+    evaluate_values(do_reverse, context, args)
+}
+
+/**
+ * Returns true if a list has an element that equals a value (see `logic::values_equal()`)
+ */
+fn contains(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_contains(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2))
+                .and_then(|()| list_operand(operands, 0))
+                .map(|elements| {
+                    let found = elements.iter().any(|e| values_equal(e, &operands[1]));
+                    Value::Boolean(found)
+                }),
+        )
+    }
+
+    evaluate_values(do_contains, context, args)
+}
+
+const LIST: &[ValueKind] = &[ValueKind::List];
+const INTEGER: &[ValueKind] = &[ValueKind::Integer];
+const ANY: &[ValueKind] = &[];
+
+/// The list operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "list",
+        Operation::new(
+            "list",
+            list,
+            null_registrar,
+            Signature::any(Arity::Variadic { required: 0 }),
+        ),
+    ),
+    (
+        "len",
+        Operation::new(
+            "len",
+            len,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[LIST]),
+        ),
+    ),
+    (
+        "nth",
+        Operation::new(
+            "nth",
+            nth,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[LIST, INTEGER]),
+        ),
+    ),
+    (
+        "head",
+        Operation::new(
+            "head",
+            head,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[LIST]),
+        ),
+    ),
+    (
+        "tail",
+        Operation::new(
+            "tail",
+            tail,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[LIST]),
+        ),
+    ),
+    (
+        "cons",
+        Operation::new(
+            "cons",
+            cons,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[ANY, LIST]),
+        ),
+    ),
+    (
+        "concat",
+        Operation::new(
+            "concat",
+            concat,
+            null_registrar,
+            Signature::new(Arity::Variadic { required: 0 }, &[LIST]),
+        ),
+    ),
+    (
+        "slice",
+        Operation::new(
+            "slice",
+            slice,
+            null_registrar,
+            Signature::new(Arity::Fixed(3), &[LIST, INTEGER]),
+        ),
+    ),
+    (
+        "reverse",
+        Operation::new(
+            "reverse",
+            reverse,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[LIST]),
+        ),
+    ),
+    (
+        "contains",
+        Operation::new(
+            "contains",
+            contains,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[LIST, ANY]),
+        ),
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+
+    /**
+     * Applies the list operation with the given name
+     */
+    fn evaluate(name: &str, operands: &[Value]) -> ValueResult {
+        let operation = &OPERATIONS.iter().find(|&&(n, _)| n == name).unwrap().1;
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let operands = operands.iter().cloned().map(Ok).collect::<Vec<_>>();
+        match operation.evaluate(&context, &operands) {
+            Total(result) => result,
+            _ => panic!("{} didn't finish", name),
+        }
+    }
+
+    fn value(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap().to_string()
+    }
+
+    fn error(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap_err().cause().to_string()
+    }
+
+    fn list(values: &[i64]) -> Value {
+        Value::List(Rc::new(values.iter().map(|&v| Value::Integer(v)).collect()))
+    }
+
+    #[test]
+    fn sequence_operations() {
+        let abc = || list(&[1, 2, 3]);
+        assert_eq!(
+            value("list", &[Value::Integer(1), Value::Boolean(true)]),
+            "[1, true]"
+        );
+        assert_eq!(value("len", &[abc()]), "3");
+        assert_eq!(value("nth", &[abc(), Value::Integer(2)]), "3");
+        assert_eq!(value("head", &[abc()]), "1");
+        assert_eq!(value("tail", &[abc()]), "[2, 3]");
+        assert_eq!(value("cons", &[Value::Integer(0), abc()]), "[0, 1, 2, 3]");
+        assert_eq!(
+            value("concat", &[abc(), list(&[]), list(&[4])]),
+            "[1, 2, 3, 4]"
+        );
+        assert_eq!(
+            value("slice", &[abc(), Value::Integer(1), Value::Integer(3)]),
+            "[2, 3]"
+        );
+        assert_eq!(value("reverse", &[abc()]), "[3, 2, 1]");
+        assert_eq!(value("contains", &[abc(), Value::Integer(2)]), "true");
+        assert_eq!(value("contains", &[abc(), Value::Float(2.0)]), "false");
+    }
+
+    #[test]
+    fn bad_indices_fail() {
+        let abc = || list(&[1, 2, 3]);
+        let index = |i: i64| Value::Integer(i);
+        assert_eq!(
+            error("nth", &[abc(), index(3)]),
+            "index 3 is out of range for a length of 3"
+        );
+        assert_eq!(
+            error("nth", &[abc(), index(-1)]),
+            "index -1 is out of range for a length of 3"
+        );
+        assert_eq!(
+            error("slice", &[abc(), index(2), index(1)]),
+            "index 2 is out of range for a length of 3"
+        );
+        assert_eq!(value("slice", &[abc(), index(3), index(3)]), "[]");
+        assert!(evaluate("head", &[list(&[])]).is_err());
+        assert!(evaluate("tail", &[list(&[])]).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+/*!
+ * Comparison and boolean operations
+ *
+ * Equality (`eq` and `ne`) is defined between any two values. Values of different kinds are
+ * never equal (so an integer never equals a float), integers are equal if they have the same
+ * numeric value (regardless of how they are stored), floats follow IEEE 754 (so NaN isn't equal
+ * to itself), lists are equal if they have the same length and equal elements, maps are equal if
+ * they have the same keys with equal values (regardless of the order of their keys), and functions
+ * are only equal to themselves.
+ *
+ * Ordering (`lt`, `le`, `gt` and `ge`) is only defined between values of the same kind:
+ *
+ * - Integers are ordered numerically.
+ * - Floats are ordered numerically, except that NaN can't be ordered.
+ * - Strings are ordered lexicographically by code point.
+ * - Booleans are ordered with `false` before `true`.
+ * - Lists are ordered lexicographically by their elements, which must be comparable. A list comes
+ *   before any longer list that starts with the same elements.
+ * - Maps and functions can't be ordered.
+ *
+ * Ordering values of different kinds, maps or functions produces an `IncomparableValues` error,
+ * and ordering NaN produces an `UnorderedNaN` error.
+ *
+ * The boolean operations (`not`, `and`, `or` and `xor`) only accept booleans. `and`, `or` and
+ * `xor` take any number of operands; `xor` is true if an odd number of its operands are true.
+ * `and` and `or` are lazy: they evaluate their operands from left to right and stop as soon as the
+ * result is known, so the remaining operands are never built.
+ */
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use base::arithmetic::compare_integers;
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::value::{evaluate_values, lazy_condition, null_registrar};
+use base::value::{
+    LazyStep, Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * Returns true if two values are equal
+ */
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a == b,
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Boolean(a), Value::Boolean(b)) => a == b,
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::Map(a), Value::Map(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| b.get(key).is_some_and(|v| values_equal(value, v)))
+        }
+        (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+        _ if a.kind() == ValueKind::Integer && b.kind() == ValueKind::Integer => {
+            compare_integers(a, b) == Ordering::Equal
+        }
+        _ => false,
+    }
+}
+
+/**
+ * Compares two values of the same kind
+ */
+pub fn compare_values(a: &Value, b: &Value) -> Result<Ordering, ValueErrorCause> {
+    match (a, b) {
+        (Value::Float(x), Value::Float(y)) => x.partial_cmp(y).ok_or(ValueErrorCause::UnorderedNaN),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+        (Value::List(a), Value::List(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match compare_values(a, b)? {
+                    Ordering::Equal => {}
+                    ordering => return Ok(ordering),
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
+        }
+        _ if a.kind() == ValueKind::Integer && b.kind() == ValueKind::Integer => {
+            Ok(compare_integers(a, b))
+        }
+        _ => Err(ValueErrorCause::IncomparableValues {
+            left: a.kind(),
+            right: b.kind(),
+        }),
+    }
+}
+
+/**
+ * Applies a comparison to a pair of operands
+ */
+fn comparison_op(
+    op: fn(&Value, &Value) -> Result<bool, ValueErrorCause>,
+    operands: &[Value],
+) -> EvaluationResult<ValueResult> {
+    if operands.len() != 2 {
+        return Total(Err(ValueError::new(
+            ValueErrorCause::WrongNumberOfOperandsForOperation {
+                expected: 2,
+                found: operands.len(),
+            },
+        )));
+    }
+    Total(
+        op(&operands[0], &operands[1])
+            .map(Value::Boolean)
+            .map_err(ValueError::new),
+    )
+}
+
+/**
+ * Extracts the values of boolean operands
+ *
+ * At least `required` operands must be given.
+ */
+fn boolean_operands(operands: &[Value], required: usize) -> Result<Vec<bool>, ValueError> {
+    if operands.len() < required {
+        return Err(ValueError::new(
+            ValueErrorCause::WrongNumberOfOperandsForOperation {
+                expected: required,
+                found: operands.len(),
+            },
+        ));
+    }
+
+    operands
+        .iter()
+        .enumerate()
+        .map(|(index, operand)| match *operand {
+            Value::Boolean(value) => Ok(value),
+            _ => {
+                Err(ValueError::new(ValueErrorCause::WrongTypesForOperation)
+                    .with_operand_index(index))
+            }
+        })
+        .collect()
+}
+
+fn true_value(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_true(_: &EvaluationContext, _: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(Ok(Value::Boolean(true)))
+    }
+
+    evaluate_values(do_true, context, args)
+}
+
+fn false_value(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_false(_: &EvaluationContext, _: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(Ok(Value::Boolean(false)))
+    }
+
+    evaluate_values(do_false, context, args)
+}
+
+fn eq(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_eq(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(|a, b| Ok(values_equal(a, b)), operands)
+    }
+
+    evaluate_values(do_eq, context, args)
+}
+
+fn ne(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_ne(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(|a, b| Ok(!values_equal(a, b)), operands)
+    }
+
+    evaluate_values(do_ne, context, args)
+}
+
+fn lt(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_lt(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(
+            |a, b| compare_values(a, b).map(|o| o == Ordering::Less),
+            operands,
+        )
+    }
+
+    evaluate_values(do_lt, context, args)
+}
+
+fn le(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_le(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(
+            |a, b| compare_values(a, b).map(|o| o != Ordering::Greater),
+            operands,
+        )
+    }
+
+    evaluate_values(do_le, context, args)
+}
+
+fn gt(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_gt(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(
+            |a, b| compare_values(a, b).map(|o| o == Ordering::Greater),
+            operands,
+        )
+    }
+
+    evaluate_values(do_gt, context, args)
+}
+
+fn ge(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_ge(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        comparison_op(
+            |a, b| compare_values(a, b).map(|o| o != Ordering::Less),
+            operands,
+        )
+    }
+
+    evaluate_values(do_ge, context, args)
+}
+
+fn not(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_not(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        if operands.len() != 1 {
+            return Total(Err(ValueError::new(
+                ValueErrorCause::WrongNumberOfOperandsForOperation {
+                    expected: 1,
+                    found: operands.len(),
+                },
+            )));
+        }
+        Total(boolean_operands(operands, 1).map(|values| Value::Boolean(!values[0])))
+    }
+
+    evaluate_values(do_not, context, args)
+}
+
+/**
+ * Evaluates boolean operands in order until one of them equals `stop`
+ *
+ * Returns `stop` if an operand equals it, and its opposite otherwise.
+ */
+fn short_circuit(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    stop: bool,
+) -> LazyStep {
+    for index in 0..operands.len() {
+        match lazy_condition(context, operands, index) {
+            Ok(value) if value == stop => return LazyStep::Total(Ok(Value::Boolean(stop))),
+            Ok(_) => {}
+            Err(step) => return step,
+        }
+    }
+    LazyStep::Total(Ok(Value::Boolean(!stop)))
+}
+
+fn and(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    short_circuit(context, operands, false)
+}
+
+fn or(context: &EvaluationContext, operands: &[Option<ValueResult>]) -> LazyStep {
+    short_circuit(context, operands, true)
+}
+
+fn xor(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_xor(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            boolean_operands(operands, 1)
+                .map(|values| Value::Boolean(values.into_iter().fold(false, |a, b| a != b))),
+        )
+    }
+
+    evaluate_values(do_xor, context, args)
+}
+
+const NULLARY: Signature = Signature::any(Arity::Fixed(0));
+const COMPARISON: Signature = Signature::any(Arity::Fixed(2));
+const UNARY: Signature = Signature::new(Arity::Fixed(1), &[&[ValueKind::Boolean]]);
+const VARIADIC: Signature =
+    Signature::new(Arity::Variadic { required: 1 }, &[&[ValueKind::Boolean]]);
+
+/// The comparison and boolean operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "true",
+        Operation::new("true", true_value, null_registrar, NULLARY),
+    ),
+    (
+        "false",
+        Operation::new("false", false_value, null_registrar, NULLARY),
+    ),
+    ("eq", Operation::new("eq", eq, null_registrar, COMPARISON)),
+    ("ne", Operation::new("ne", ne, null_registrar, COMPARISON)),
+    ("lt", Operation::new("lt", lt, null_registrar, COMPARISON)),
+    ("le", Operation::new("le", le, null_registrar, COMPARISON)),
+    ("gt", Operation::new("gt", gt, null_registrar, COMPARISON)),
+    ("ge", Operation::new("ge", ge, null_registrar, COMPARISON)),
+    ("not", Operation::new("not", not, null_registrar, UNARY)),
+    ("and", Operation::lazy("and", and, VARIADIC)),
+    ("or", Operation::lazy("or", or, VARIADIC)),
+    ("xor", Operation::new("xor", xor, null_registrar, VARIADIC)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(values: &[Value]) -> Value {
+        Value::List(Rc::new(values.to_vec()))
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn equality_is_defined_between_any_values() {
+        let big = Value::parse_integer("0x10000000000000000").unwrap();
+        assert!(values_equal(&Value::Integer(1), &Value::Integer(1)));
+        assert!(values_equal(
+            &big,
+            &Value::parse_integer("18446744073709551616").unwrap()
+        ));
+        assert!(!values_equal(&big, &Value::Integer(1)));
+        assert!(!values_equal(&Value::Integer(1), &Value::Float(1.0)));
+        assert!(!values_equal(&Value::Integer(1), &string("1")));
+        assert!(!values_equal(
+            &Value::Float(f64::NAN),
+            &Value::Float(f64::NAN)
+        ));
+        assert!(values_equal(
+            &list(&[Value::Integer(1), string("a")]),
+            &list(&[Value::Integer(1), string("a")])
+        ));
+        assert!(!values_equal(&list(&[Value::Integer(1)]), &list(&[])));
+    }
+
+    #[test]
+    fn values_of_the_same_kind_are_ordered() {
+        let order = |a: &Value, b: &Value| compare_values(a, b).unwrap();
+        assert_eq!(
+            order(&Value::Integer(-1), &Value::Integer(1)),
+            Ordering::Less
+        );
+        assert_eq!(order(&string("b"), &string("ab")), Ordering::Greater);
+        assert_eq!(
+            order(&Value::Boolean(false), &Value::Boolean(true)),
+            Ordering::Less
+        );
+        assert_eq!(
+            order(
+                &list(&[Value::Integer(1)]),
+                &list(&[Value::Integer(1), string("a")])
+            ),
+            Ordering::Less
+        );
+        assert_eq!(
+            order(&Value::Float(0.5), &Value::Float(-0.5)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn incomparable_values_fail() {
+        let cause = compare_values(&Value::Integer(1), &string("1")).unwrap_err();
+        assert!(matches!(cause, ValueErrorCause::IncomparableValues { .. }));
+        assert_eq!(cause.to_string(), "cannot compare integer with string");
+
+        let nan = Value::Float(f64::NAN);
+        let cause = compare_values(&nan, &Value::Float(1.0)).unwrap_err();
+        assert!(matches!(cause, ValueErrorCause::UnorderedNaN));
+        assert_eq!(cause.to_string(), "cannot order NaN");
+        assert!(matches!(
+            compare_values(&list(&[nan]), &list(&[Value::Float(1.0)])),
+            Err(ValueErrorCause::UnorderedNaN)
+        ));
+    }
+}
@@ -0,0 +1,484 @@
+/*!
+ * Map values and operations
+ *
+ * Maps associate keys with values and remember the order in which their keys were first
+ * inserted. Like lists, maps are immutable; operations that "modify" a map return a new one.
+ *
+ * Keys must be hashable: integers, strings, booleans and lists of hashable values can be keys,
+ * but floats, maps and functions can't. Keys are compared with `logic::values_equal()`.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::logic::values_equal;
+use base::value::{check_operand_count, evaluate_values, null_registrar};
+use base::value::{
+    Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * An insertion-ordered map from values to values
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    /// Maps key hashes to the indices of the entries with those hashes
+    index: HashMap<u64, Vec<usize>>,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /**
+     * Returns the entries in insertion order
+     */
+    pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, (Value, Value)> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.position(key).map(|position| &self.entries[position].1)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.position(key).is_some()
+    }
+
+    /**
+     * Sets the value for a key
+     *
+     * A key that is already present keeps its position. Fails if the key isn't hashable.
+     */
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<(), ValueErrorCause> {
+        let hash = match hash_key(&key) {
+            Some(hash) => hash,
+            None => return Err(ValueErrorCause::UnhashableKey { kind: key.kind() }),
+        };
+        match self.position(&key) {
+            Some(position) => self.entries[position].1 = value,
+            None => {
+                self.index.entry(hash).or_default().push(self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Removes a key, returning its value if it was present
+     */
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let position = self.position(key)?;
+        let (_, value) = self.entries.remove(position);
+        // The positions of all of the following entries have changed.
+        self.index.clear();
+        for (position, (key, _)) in self.entries.iter().enumerate() {
+            let hash = hash_key(key).unwrap();
+            self.index.entry(hash).or_default().push(position);
+        }
+        Some(value)
+    }
+
+    fn position(&self, key: &Value) -> Option<usize> {
+        let candidates = self.index.get(&hash_key(key)?)?;
+        candidates
+            .iter()
+            .cloned()
+            .find(|&position| values_equal(&self.entries[position].0, key))
+    }
+}
+
+/**
+ * Hashes a map key, returning None if the value isn't hashable
+ *
+ * Equal keys (see `logic::values_equal()`) have equal hashes.
+ */
+fn hash_key(key: &Value) -> Option<u64> {
+    fn hash_into(key: &Value, hasher: &mut DefaultHasher) -> Option<()> {
+        key.kind().hash(hasher);
+        match *key {
+            // Integers are always stored in their smallest representation, so equal integers
+            // have the same variant.
+            Value::Integer(value) => value.hash(hasher),
+            Value::BigInt(ref value) => value.hash(hasher),
+            Value::String(ref value) => value.hash(hasher),
+            Value::Boolean(value) => value.hash(hasher),
+            Value::List(ref elements) => {
+                elements.len().hash(hasher);
+                for element in elements.iter() {
+                    hash_into(element, hasher)?;
+                }
+            }
+            // NaN isn't equal to itself, so floats can't be found again reliably.
+            Value::Float(_) | Value::Map(_) | Value::Function(_) => return None,
+        }
+        Some(())
+    }
+
+    let mut hasher = DefaultHasher::new();
+    hash_into(key, &mut hasher)?;
+    Some(hasher.finish())
+}
+
+/**
+ * Returns a map operand
+ */
+fn map_operand(operands: &[Value], index: usize) -> Result<&Rc<Map>, ValueError> {
+    match operands[index] {
+        Value::Map(ref map) => Ok(map),
+        _ => {
+            Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index))
+        }
+    }
+}
+
+/**
+ * Builds a map from alternating keys and values
+ */
+fn map(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_map(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        if operands.len() % 2 == 1 {
+            return Total(Err(ValueError::new(
+                ValueErrorCause::WrongNumberOfOperandsForOperation {
+                    expected: operands.len() + 1,
+                    found: operands.len(),
+                },
+            )));
+        }
+        let mut map = Map::new();
+        for (index, pair) in operands.chunks(2).enumerate() {
+            if let Err(cause) = map.insert(pair[0].clone(), pair[1].clone()) {
+                return Total(Err(ValueError::new(cause).with_operand_index(index * 2)));
+            }
+        }
+        Total(Ok(Value::Map(Rc::new(map))))
+    }
+
+    evaluate_values(do_map, context, args)
+}
+
+/**
+ * Returns the value for a key
+ *
+ * If the key is missing, returns the third operand if there is one and fails otherwise.
+ */
+fn get(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_get(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(check_operand_count(operands, GET_ARITY).and_then(|()| {
+            match map_operand(operands, 0)?.get(&operands[1]) {
+                Some(value) => Ok(value.clone()),
+                None => match operands.get(2) {
+                    Some(default) => Ok(default.clone()),
+                    None => Err(ValueError::new(ValueErrorCause::MissingKey {
+                        key: operands[1].clone(),
+                    })
+                    .with_operand_index(1)),
+                },
+            }
+        }))
+    }
+
+    evaluate_values(do_get, context, args)
+}
+
+/**
+ * Returns a copy of a map with a key set to a value
+ */
+fn assoc(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_assoc(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(3)).and_then(|()| {
+                let mut map = (**map_operand(operands, 0)?).clone();
+                match map.insert(operands[1].clone(), operands[2].clone()) {
+                    Ok(()) => Ok(Value::Map(Rc::new(map))),
+                    Err(cause) => Err(ValueError::new(cause).with_operand_index(1)),
+                }
+            }),
+        )
+    }
+
+    evaluate_values(do_assoc, context, args)
+}
+
+/**
+ * Returns a copy of a map without a key (which doesn't have to be present)
+ */
+fn dissoc(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_dissoc(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2)).and_then(|()| {
+                let map = map_operand(operands, 0)?;
+                if !map.contains_key(&operands[1]) {
+                    return Ok(operands[0].clone());
+                }
+                let mut map = (**map).clone();
+                map.remove(&operands[1]);
+                Ok(Value::Map(Rc::new(map)))
+            }),
+        )
+    }
+
+    evaluate_values(do_dissoc, context, args)
+}
+
+/**
+ * Returns a map's keys as a list (in insertion order)
+ */
+fn keys(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_keys(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1))
+                .and_then(|()| map_operand(operands, 0))
+                .map(|map| Value::List(Rc::new(map.iter().map(|e| e.0.clone()).collect()))),
+        )
+    }
+
+    evaluate_values(do_keys, context, args)
+}
+
+/**
+ * Returns a map's values as a list (in the order of their keys)
+ */
+fn values(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_values(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(1))
+                .and_then(|()| map_operand(operands, 0))
+                .map(|map| Value::List(Rc::new(map.iter().map(|e| e.1.clone()).collect()))),
+        )
+    }
+
+    evaluate_values(do_values, context, args)
+}
+
+fn has_key(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_has_key(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2))
+                .and_then(|()| map_operand(operands, 0))
+                .map(|map| Value::Boolean(map.contains_key(&operands[1]))),
+        )
+    }
+
+    evaluate_values(do_has_key, context, args)
+}
+
+/**
+ * Combines any number of maps
+ *
+ * When several maps have the same key, the value from the last one wins, but the key keeps the
+ * position from the first one.
+ */
+fn merge(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_merge(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        let mut result = Map::new();
+        for index in 0..operands.len() {
+            let map = match map_operand(operands, index) {
+                Ok(map) => map,
+                Err(error) => return Total(Err(error)),
+            };
+            for (key, value) in map.iter() {
+                // Keys that are already in a map must be hashable.
+                result.insert(key.clone(), value.clone()).unwrap();
+            }
+        }
+        Total(Ok(Value::Map(Rc::new(result))))
+    }
+
+    evaluate_values(do_merge, context, args)
+}
+
+const GET_ARITY: Arity = Arity::Optional {
+    required: 2,
+    optional: 1,
+};
+
+const MAP: &[ValueKind] = &[ValueKind::Map];
+const ANY: &[ValueKind] = &[];
+
+/// The map operations, by name
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "map",
+        Operation::new(
+            "map",
+            map,
+            null_registrar,
+            Signature::any(Arity::Variadic { required: 0 }),
+        ),
+    ),
+    (
+        "get",
+        Operation::new(
+            "get",
+            get,
+            null_registrar,
+            Signature::new(GET_ARITY, &[MAP, ANY]),
+        ),
+    ),
+    (
+        "assoc",
+        Operation::new(
+            "assoc",
+            assoc,
+            null_registrar,
+            Signature::new(Arity::Fixed(3), &[MAP, ANY]),
+        ),
+    ),
+    (
+        "dissoc",
+        Operation::new(
+            "dissoc",
+            dissoc,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[MAP, ANY]),
+        ),
+    ),
+    (
+        "keys",
+        Operation::new(
+            "keys",
+            keys,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[MAP]),
+        ),
+    ),
+    (
+        "values",
+        Operation::new(
+            "values",
+            values,
+            null_registrar,
+            Signature::new(Arity::Fixed(1), &[MAP]),
+        ),
+    ),
+    (
+        "has_key",
+        Operation::new(
+            "has_key",
+            has_key,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[MAP, ANY]),
+        ),
+    ),
+    (
+        "merge",
+        Operation::new(
+            "merge",
+            merge,
+            null_registrar,
+            Signature::new(Arity::Variadic { required: 0 }, &[MAP]),
+        ),
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+
+    /**
+     * Applies the map operation with the given name
+     */
+    fn evaluate(name: &str, operands: &[Value]) -> ValueResult {
+        let operation = &OPERATIONS.iter().find(|&&(n, _)| n == name).unwrap().1;
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let operands = operands.iter().cloned().map(Ok).collect::<Vec<_>>();
+        match operation.evaluate(&context, &operands) {
+            Total(result) => result,
+            _ => panic!("{} didn't finish", name),
+        }
+    }
+
+    fn value(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap().to_string()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    fn map(entries: &[(&str, i64)]) -> Value {
+        let mut map = Map::new();
+        for &(key, value) in entries {
+            map.insert(string(key), Value::Integer(value)).unwrap();
+        }
+        Value::Map(Rc::new(map))
+    }
+
+    #[test]
+    fn maps_keep_insertion_order() {
+        let mut map = Map::new();
+        for (i, key) in ["b", "a", "c"].iter().enumerate() {
+            map.insert(string(key), Value::Integer(i as i64)).unwrap();
+        }
+        map.insert(string("a"), Value::Integer(9)).unwrap();
+        assert_eq!(map.remove(&string("b")).unwrap().to_string(), "0");
+        assert_eq!(
+            Value::Map(Rc::new(map.clone())).to_string(),
+            "{\"a\": 9, \"c\": 2}"
+        );
+        assert_eq!(map.get(&string("c")).unwrap().to_string(), "2");
+        assert!(!map.contains_key(&string("b")));
+    }
+
+    #[test]
+    fn keys_must_be_hashable() {
+        let mut map = Map::new();
+        let big = Value::parse_integer("0x10000000000000000").unwrap();
+        map.insert(big, Value::Boolean(true)).unwrap();
+        let same = Value::parse_integer("18446744073709551616").unwrap();
+        assert!(map.contains_key(&same));
+        assert!(matches!(
+            map.insert(Value::Float(1.0), Value::Integer(1)),
+            Err(ValueErrorCause::UnhashableKey {
+                kind: ValueKind::Float
+            })
+        ));
+    }
+
+    #[test]
+    fn map_operations() {
+        let ab = || map(&[("a", 1), ("b", 2)]);
+        assert_eq!(
+            value("map", &[string("a"), Value::Integer(1)]),
+            "{\"a\": 1}"
+        );
+        assert_eq!(value("get", &[ab(), string("b")]), "2");
+        assert_eq!(value("get", &[ab(), string("z"), Value::Integer(0)]), "0");
+        assert!(matches!(
+            evaluate("get", &[ab(), string("z")]).unwrap_err().cause(),
+            ValueErrorCause::MissingKey { .. }
+        ));
+        assert_eq!(
+            value("assoc", &[ab(), string("c"), Value::Integer(3)]),
+            "{\"a\": 1, \"b\": 2, \"c\": 3}"
+        );
+        assert_eq!(value("dissoc", &[ab(), string("a")]), "{\"b\": 2}");
+        assert_eq!(value("keys", &[ab()]), "[\"a\", \"b\"]");
+        assert_eq!(value("values", &[ab()]), "[1, 2]");
+        assert_eq!(value("has_key", &[ab(), string("a")]), "true");
+        assert_eq!(
+            value("merge", &[ab(), map(&[("c", 3), ("a", 4)])]),
+            "{\"a\": 4, \"b\": 2, \"c\": 3}"
+        );
+    }
+}
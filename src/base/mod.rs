@@ -1,5 +1,17 @@
+pub mod arithmetic;
+pub mod bigint;
 pub mod context;
+pub mod control;
+pub mod convert;
+pub mod diagnostic;
+pub mod explain;
 pub mod expression;
+pub mod function;
+pub mod json;
+pub mod list;
+pub mod logic;
+pub mod map;
 pub mod source;
+pub mod string;
 pub mod symbol;
 pub mod value;
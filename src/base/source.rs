@@ -1,23 +1,108 @@
+use std::cell::RefCell;
 use std::cmp;
 use std::error;
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
 
+/**
+ * Identifies a `SourceText` within a `SourceMap`
+ *
+ * Sources that haven't been added to a `SourceMap` have the default ID, `SourceId::DETACHED`,
+ * which no `SourceMap` ever hands out.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(u32);
+
+impl SourceId {
+    /// The ID of every source that isn't in a `SourceMap`
+    pub const DETACHED: SourceId = SourceId(0);
+
+    pub fn is_detached(self) -> bool {
+        self == SourceId::DETACHED
+    }
+}
+
+/// The length in bytes of the largest text that a `SourceText` can hold
+pub const MAX_SOURCE_LEN: usize = u32::MAX as usize;
+
 #[derive(Debug)]
 pub struct SourceText {
+    /// The name of the source (usually a file path)
+    name: Option<Box<str>>,
+    /// The source's ID in its `SourceMap`
+    id: SourceId,
     text: String,
+    /*
+     * The byte offset at which each line starts
+     *
+     * Built the first time a line number is requested.
+     */
+    line_starts: RefCell<Option<Rc<[usize]>>>,
 }
 
 impl SourceText {
-    pub fn new(text: String) -> SourceText {
-        SourceText { text }
+    /**
+     * Creates a source without a name
+     *
+     * Returns an `InvalidData` error if the text is longer than `MAX_SOURCE_LEN` bytes.
+     */
+    pub fn new(text: String) -> io::Result<SourceText> {
+        if text.len() > MAX_SOURCE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "source text is 4 GiB or larger",
+            ));
+        }
+        Ok(SourceText {
+            name: None,
+            id: SourceId::DETACHED,
+            text,
+            line_starts: RefCell::new(None),
+        })
+    }
+
+    pub fn with_name(name: &str, text: String) -> io::Result<SourceText> {
+        Ok(SourceText {
+            name: Some(name.into()),
+            ..SourceText::new(text)?
+        })
+    }
+
+    /**
+     * Reads a source file, using its path as the source's name
+     */
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<SourceText> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        SourceText::with_name(&path.to_string_lossy(), text)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn id(&self) -> SourceId {
+        self.id
     }
 
     pub fn text(&self) -> &str {
         &self.text
     }
 
+    /**
+     * Returns the text covered by a span
+     *
+     * The span must refer to this source.
+     */
+    pub fn slice(&self, span: Span) -> &str {
+        debug_assert_eq!(span.source, self.id);
+        &self.text[span.start()..span.end()]
+    }
+
     pub fn len(&self) -> usize {
         self.text.len()
     }
@@ -25,10 +110,75 @@ impl SourceText {
     pub fn is_empty(&self) -> bool {
         self.text.is_empty()
     }
+
+    fn line_starts(&self) -> Rc<[usize]> {
+        let mut line_starts = self.line_starts.borrow_mut();
+        let text = &self.text;
+        let starts = line_starts.get_or_insert_with(|| {
+            let mut starts = vec![0];
+            starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+            starts.into()
+        });
+        Rc::clone(starts)
+    }
+
+    /**
+     * Returns the number of lines in the text
+     */
+    pub fn line_count(&self) -> usize {
+        self.line_starts().len()
+    }
+
+    /**
+     * Returns the text of a line (without its line terminator)
+     *
+     * Lines are numbered from 1.
+     */
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let starts = self.line_starts();
+        if line == 0 || line > starts.len() {
+            return None;
+        }
+        let start = starts[line - 1];
+        let end = starts.get(line).map_or(self.text.len(), |next| next - 1);
+        let text = &self.text[start..end];
+        Some(text.trim_end_matches('\r'))
+    }
+
+    /**
+     * Returns the line and column of a byte offset in the text
+     */
+    pub fn position(&self, offset: usize) -> LineColumn {
+        let starts = self.line_starts();
+        let line = match starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let column = self.text[starts[line]..offset].chars().count();
+        LineColumn {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+}
+
+/**
+ * A position in a `SourceText`
+ *
+ * Lines and columns are numbered from 1, and columns are counted in chars.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for LineColumn {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}:{}", self.line, self.column)
+    }
 }
 
-// TODO: allow retrieval of line numbers.
-// Line nums must be calculated before we get our hands on a SourceLocation pointing into the line.
 #[derive(Clone, Debug)]
 pub struct SourceLocation {
     source: Rc<SourceText>,
@@ -45,10 +195,57 @@ impl SourceLocation {
         }
     }
 
+    /**
+     * Creates a SourceLocation covering a span
+     *
+     * The span must refer to `source`.
+     */
+    pub fn from_span(source: Rc<SourceText>, span: Span) -> SourceLocation {
+        debug_assert_eq!(span.source, source.id);
+        SourceLocation::new(source, span.start(), span.len())
+    }
+
+    /**
+     * Returns a compact `Span` covering the location
+     */
+    pub fn to_span(&self) -> Span {
+        Span::new(self.source.id, self.offset, self.length)
+    }
+
+    pub fn source(&self) -> &Rc<SourceText> {
+        &self.source
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
     pub fn end(&self) -> usize {
         self.offset + self.length
     }
 
+    /**
+     * Returns the line and column at which the location starts
+     */
+    pub fn start_position(&self) -> LineColumn {
+        self.source.position(self.offset)
+    }
+
+    /**
+     * Returns the line and column at which the location ends (i.e. just past its last char)
+     */
+    pub fn end_position(&self) -> LineColumn {
+        self.source.position(self.end())
+    }
+
     /**
      * Returns a new SourceLocation that covers the span of both given SourceLocations
      *
@@ -75,8 +272,154 @@ impl SourceLocation {
 
 impl Display for SourceLocation {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        // TODO: improve the display format.
-        write!(formatter, "{}:{}", self.offset, self.offset + self.length)
+        if let Some(name) = self.source.name() {
+            write!(formatter, "{}:", name)?;
+        }
+        write!(formatter, "{}", self.start_position())
+    }
+}
+
+/**
+ * A compact reference to a range of bytes in a `SourceText`
+ *
+ * Unlike a `SourceLocation`, a Span doesn't own a reference to its source; it must be resolved
+ * through a `SourceRegistry` to get at the text.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    source: SourceId,
+    start: u32,
+    length: u32,
+}
+
+impl Span {
+    pub fn new(source: SourceId, start: usize, length: usize) -> Span {
+        // Offsets within a `SourceText` always fit, as its length is at most `MAX_SOURCE_LEN`.
+        debug_assert!(start + length <= MAX_SOURCE_LEN);
+        Span {
+            source,
+            start: start as u32,
+            length: length as u32,
+        }
+    }
+
+    pub fn source(&self) -> SourceId {
+        self.source
+    }
+
+    pub fn start(&self) -> usize {
+        self.start as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn end(&self) -> usize {
+        self.start() + self.len()
+    }
+
+    /**
+     * Returns a new Span that covers both given Spans
+     *
+     * `span1` and `span2` must refer to the same source.
+     */
+    pub fn join(span1: Span, span2: Span) -> Span {
+        assert_eq!(span1.source, span2.source);
+
+        let start = cmp::min(span1.start, span2.start);
+        let end = cmp::max(span1.end(), span2.end()) as u32;
+
+        Span {
+            source: span1.source,
+            start,
+            length: end - start,
+        }
+    }
+}
+
+/**
+ * Looks up sources by their IDs so that `Span`s can be resolved
+ */
+pub trait SourceRegistry {
+    fn source(&self, id: SourceId) -> Option<&Rc<SourceText>>;
+
+    fn resolve(&self, span: Span) -> Option<SourceLocation> {
+        self.source(span.source)
+            .map(|source| SourceLocation::from_span(Rc::clone(source), span))
+    }
+}
+
+/**
+ * A lone source acts as a registry that contains only itself.
+ */
+impl SourceRegistry for Rc<SourceText> {
+    fn source(&self, id: SourceId) -> Option<&Rc<SourceText>> {
+        if id == self.id {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+/**
+ * Owns all of the `SourceText`s that have been loaded and assigns them IDs
+ */
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    sources: Vec<Rc<SourceText>>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { sources: vec![] }
+    }
+
+    /**
+     * Adds a source to the map and returns its new ID
+     */
+    pub fn add(&mut self, mut source: SourceText) -> SourceId {
+        // IDs start at 1 so that they can't collide with `SourceId::DETACHED`.
+        assert!(self.sources.len() < u32::MAX as usize);
+        let id = SourceId(self.sources.len() as u32 + 1);
+        source.id = id;
+        self.sources.push(Rc::new(source));
+        id
+    }
+
+    /**
+     * Reads a source file and adds it to the map
+     */
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<SourceId> {
+        Ok(self.add(SourceText::from_file(path)?))
+    }
+
+    pub fn get(&self, id: SourceId) -> Option<&Rc<SourceText>> {
+        id.0.checked_sub(1)
+            .and_then(|index| self.sources.get(index as usize))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<SourceText>> {
+        self.sources.iter()
+    }
+}
+
+impl SourceRegistry for SourceMap {
+    fn source(&self, id: SourceId) -> Option<&Rc<SourceText>> {
+        self.get(id)
     }
 }
 
@@ -84,5 +427,88 @@ impl Display for SourceLocation {
  * An error that is associated with a specific location in a `SourceText`
  */
 pub trait Error: error::Error {
-    fn location(&self) -> SourceLocation;
+    fn location(&self) -> &SourceLocation;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: usize, column: usize) -> LineColumn {
+        LineColumn { line, column }
+    }
+
+    #[test]
+    fn positions_at_line_starts() {
+        let source = SourceText::new("ab\ncd\n\nef".to_owned()).unwrap();
+        assert_eq!(source.position(0), position(1, 1));
+        assert_eq!(source.position(2), position(1, 3));
+        assert_eq!(source.position(3), position(2, 1));
+        assert_eq!(source.position(6), position(3, 1));
+        assert_eq!(source.position(7), position(4, 1));
+        assert_eq!(source.position(9), position(4, 3));
+        assert_eq!(source.line_count(), 4);
+        assert_eq!(source.line(3), Some(""));
+        assert_eq!(source.line(5), None);
+        assert_eq!(source.line(0), None);
+    }
+
+    #[test]
+    fn positions_in_crlf_text() {
+        let source = SourceText::new("ab\r\ncd\r\n".to_owned()).unwrap();
+        assert_eq!(source.position(2), position(1, 3));
+        assert_eq!(source.position(4), position(2, 1));
+        assert_eq!(source.position(5), position(2, 2));
+        assert_eq!(source.position(8), position(3, 1));
+        assert_eq!(source.line_count(), 3);
+        assert_eq!(source.line(1), Some("ab"));
+        assert_eq!(source.line(2), Some("cd"));
+        assert_eq!(source.line(3), Some(""));
+    }
+
+    #[test]
+    fn columns_count_chars() {
+        let source = SourceText::new("é\n→x".to_owned()).unwrap();
+        assert_eq!(source.position("é".len()), position(1, 2));
+        assert_eq!(source.position("é\n→".len()), position(2, 2));
+    }
+
+    #[test]
+    fn locations_display_their_start() {
+        let source = Rc::new(SourceText::with_name("a.rh", "(add\n  1)".to_owned()).unwrap());
+        let location = SourceLocation::new(Rc::clone(&source), 7, 1);
+        assert_eq!(location.to_string(), "a.rh:2:3");
+        assert_eq!(location.end_position(), position(2, 4));
+        let detached = SourceLocation::new(Rc::new(SourceText::new("x".to_owned()).unwrap()), 0, 1);
+        assert_eq!(detached.to_string(), "1:1");
+    }
+
+    #[test]
+    fn source_map_assigns_ids_from_one() {
+        let mut sources = SourceMap::new();
+        let first = sources.add(SourceText::with_name("a.rh", "(add 1 2)".to_owned()).unwrap());
+        let second = sources.add(SourceText::new("(not (true))".to_owned()).unwrap());
+        assert_ne!(first, SourceId::DETACHED);
+        assert_ne!(first, second);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources.get(first).unwrap().name(), Some("a.rh"));
+        assert_eq!(sources.get(second).unwrap().id(), second);
+        assert!(sources.get(SourceId::DETACHED).is_none());
+
+        let location = sources.resolve(Span::new(first, 5, 1)).unwrap();
+        assert_eq!(location.text(), "1");
+        assert_eq!(location.to_string(), "a.rh:1:6");
+        assert!(sources
+            .resolve(Span::new(SourceId::DETACHED, 0, 1))
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn rejects_oversized_text() {
+        // The zeroed buffer is allocated lazily, so this doesn't actually use 4 GiB of memory.
+        let text = unsafe { String::from_utf8_unchecked(vec![0; MAX_SOURCE_LEN + 1]) };
+        let error = SourceText::new(text).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
 }
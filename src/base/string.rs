@@ -0,0 +1,482 @@
+/*!
+ * String operations
+ *
+ * These operations live in the `str` namespace (so `concat` is `str.concat` or `str::concat`),
+ * which keeps them apart from the list operations with the same names.
+ *
+ * Lengths and indices count characters (Unicode scalar values), not bytes, so no operation can
+ * split a character. Indices start at 0; bad indices produce an `IndexOutOfRange` error.
+ */
+
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::Chars;
+
+use base::context::EvaluationContext;
+use base::expression::Arity;
+use base::expression::EvaluationResult;
+use base::expression::EvaluationResult::Total;
+use base::list::index_operand;
+use base::value::{check_operand_count, evaluate_values, null_registrar};
+use base::value::{
+    Operation, Signature, Value, ValueError, ValueErrorCause, ValueKind, ValueResult,
+};
+
+/**
+ * Returns the text of a string operand
+ */
+fn string_operand(operands: &[Value], index: usize) -> Result<&str, ValueError> {
+    match operands[index] {
+        Value::String(ref text) => Ok(text),
+        _ => {
+            Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index))
+        }
+    }
+}
+
+/**
+ * Applies a function to a single string operand
+ */
+fn string_unary_op(op: fn(&str) -> Value, operands: &[Value]) -> EvaluationResult<ValueResult> {
+    Total(
+        check_operand_count(operands, Arity::Fixed(1))
+            .and_then(|()| string_operand(operands, 0))
+            .map(op),
+    )
+}
+
+/**
+ * Applies a function to a pair of string operands
+ */
+fn string_binary_op(
+    op: fn(&str, &str) -> Value,
+    operands: &[Value],
+) -> EvaluationResult<ValueResult> {
+    Total(
+        check_operand_count(operands, Arity::Fixed(2)).and_then(|()| {
+            Ok(op(
+                string_operand(operands, 0)?,
+                string_operand(operands, 1)?,
+            ))
+        }),
+    )
+}
+
+/**
+ * Converts a character index to a byte offset
+ *
+ * An index equal to the number of characters maps to the end of the string.
+ */
+fn byte_offset(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+/**
+ * Joins any number of strings
+ */
+fn concat(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_concat(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        let mut result = String::new();
+        for index in 0..operands.len() {
+            match string_operand(operands, index) {
+                Ok(text) => result.push_str(text),
+                Err(error) => return Total(Err(error)),
+            }
+        }
+        Total(Ok(Value::String(result)))
+    }
+
+    evaluate_values(do_concat, context, args)
+}
+
+/**
+ * Returns the number of characters in a string
+ */
+fn len(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_len(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_unary_op(|text| Value::Integer(text.chars().count() as i64), operands)
+    }
+
+    evaluate_values(do_len, context, args)
+}
+
+/**
+ * Returns the characters from a start index up to (but not including) an end index
+ */
+fn substring(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_substring(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(3)).and_then(|()| {
+                let text = string_operand(operands, 0)?;
+                let length = text.chars().count();
+                let end = index_operand(operands, 2, length + 1, length)?;
+                let start = index_operand(operands, 1, end + 1, length)?;
+                let (start, end) = (byte_offset(text, start), byte_offset(text, end));
+                Ok(Value::String(text[start..end].to_owned()))
+            }),
+        )
+    }
+
+    evaluate_values(do_substring, context, args)
+}
+
+fn upper(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_upper(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_unary_op(|text| Value::String(text.to_uppercase()), operands)
+    }
+
+    evaluate_values(do_upper, context, args)
+}
+
+fn lower(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_lower(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_unary_op(|text| Value::String(text.to_lowercase()), operands)
+    }
+
+    evaluate_values(do_lower, context, args)
+}
+
+/**
+ * Removes whitespace from both ends of a string
+ */
+fn trim(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_trim(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_unary_op(|text| Value::String(text.trim().to_owned()), operands)
+    }
+
+    evaluate_values(do_trim, context, args)
+}
+
+/**
+ * Splits a string into a list of strings around a separator
+ *
+ * An empty separator splits the string into characters.
+ */
+fn split(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_split(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_binary_op(
+            |text, separator| {
+                let parts: Vec<Value> = if separator.is_empty() {
+                    text.chars().map(|c| Value::String(c.to_string())).collect()
+                } else {
+                    text.split(separator)
+                        .map(|part| Value::String(part.to_owned()))
+                        .collect()
+                };
+                Value::List(Rc::new(parts))
+            },
+            operands,
+        )
+    }
+
+    evaluate_values(do_split, context, args)
+}
+
+/**
+ * Joins a list of strings with a separator between them
+ */
+fn join(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_join(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(2)).and_then(|()| {
+                let wrong_type = || {
+                    ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(0)
+                };
+                let elements = match operands[0] {
+                    Value::List(ref elements) => elements,
+                    _ => return Err(wrong_type()),
+                };
+                let separator = string_operand(operands, 1)?;
+                let mut result = String::new();
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        result.push_str(separator);
+                    }
+                    match *element {
+                        Value::String(ref text) => result.push_str(text),
+                        _ => return Err(wrong_type()),
+                    }
+                }
+                Ok(Value::String(result))
+            }),
+        )
+    }
+
+    evaluate_values(do_join, context, args)
+}
+
+fn starts_with(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_starts_with(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_binary_op(
+            |text, prefix| Value::Boolean(text.starts_with(prefix)),
+            operands,
+        )
+    }
+
+    evaluate_values(do_starts_with, context, args)
+}
+
+/**
+ * Returns the character index of the first occurrence of a substring, or -1 if there is none
+ */
+fn find(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_find(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        string_binary_op(
+            |text, needle| {
+                Value::Integer(match text.find(needle) {
+                    Some(offset) => text[..offset].chars().count() as i64,
+                    None => -1,
+                })
+            },
+            operands,
+        )
+    }
+
+    evaluate_values(do_find, context, args)
+}
+
+/**
+ * Replaces every occurrence of a substring
+ */
+fn replace(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_replace(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Fixed(3)).and_then(|()| {
+                let text = string_operand(operands, 0)?;
+                let from = string_operand(operands, 1)?;
+                let to = string_operand(operands, 2)?;
+                Ok(Value::String(text.replace(from, to)))
+            }),
+        )
+    }
+
+    evaluate_values(do_replace, context, args)
+}
+
+/**
+ * Fills the `{}` placeholders in a string with the remaining operands
+ *
+ * Strings are inserted as they are; other values are inserted as they're displayed. `{{` and `}}`
+ * stand for literal braces. The number of placeholders must match the number of values.
+ */
+fn format(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_format(_: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
+        Total(
+            check_operand_count(operands, Arity::Variadic { required: 1 }).and_then(|()| {
+                let template = string_operand(operands, 0)?;
+                format_string(template.chars().peekable(), &operands[1..])
+                    .map(Value::String)
+                    .map_err(ValueError::new)
+            }),
+        )
+    }
+
+    evaluate_values(do_format, context, args)
+}
+
+fn format_string(
+    mut chars: Peekable<Chars>,
+    arguments: &[Value],
+) -> Result<String, ValueErrorCause> {
+    let mut result = String::new();
+    let mut placeholders = 0;
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().cloned()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                result.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                match arguments.get(placeholders) {
+                    Some(Value::String(text)) => result.push_str(text),
+                    Some(argument) => result.push_str(&argument.to_string()),
+                    None => {}
+                }
+                placeholders += 1;
+            }
+            _ => result.push(c),
+        }
+    }
+
+    if placeholders != arguments.len() {
+        return Err(ValueErrorCause::FormatArgumentMismatch {
+            placeholders,
+            arguments: arguments.len(),
+        });
+    }
+    Ok(result)
+}
+
+const STRING: &[ValueKind] = &[ValueKind::String];
+const UNARY: Signature = Signature::new(Arity::Fixed(1), &[STRING]);
+const BINARY: Signature = Signature::new(Arity::Fixed(2), &[STRING]);
+const TERNARY: Signature = Signature::new(Arity::Fixed(3), &[STRING]);
+
+/// The string operations, by name (within the `str` namespace)
+pub const OPERATIONS: &[(&str, Operation)] = &[
+    (
+        "concat",
+        Operation::new(
+            "str::concat",
+            concat,
+            null_registrar,
+            Signature::new(Arity::Variadic { required: 0 }, &[STRING]),
+        ),
+    ),
+    (
+        "len",
+        Operation::new("str::len", len, null_registrar, UNARY),
+    ),
+    (
+        "substring",
+        Operation::new(
+            "str::substring",
+            substring,
+            null_registrar,
+            Signature::new(Arity::Fixed(3), &[STRING, &[ValueKind::Integer]]),
+        ),
+    ),
+    (
+        "upper",
+        Operation::new("str::upper", upper, null_registrar, UNARY),
+    ),
+    (
+        "lower",
+        Operation::new("str::lower", lower, null_registrar, UNARY),
+    ),
+    (
+        "trim",
+        Operation::new("str::trim", trim, null_registrar, UNARY),
+    ),
+    (
+        "split",
+        Operation::new("str::split", split, null_registrar, BINARY),
+    ),
+    (
+        "join",
+        Operation::new(
+            "str::join",
+            join,
+            null_registrar,
+            Signature::new(Arity::Fixed(2), &[&[ValueKind::List], STRING]),
+        ),
+    ),
+    (
+        "starts_with",
+        Operation::new("str::starts_with", starts_with, null_registrar, BINARY),
+    ),
+    (
+        "find",
+        Operation::new("str::find", find, null_registrar, BINARY),
+    ),
+    (
+        "replace",
+        Operation::new("str::replace", replace, null_registrar, TERNARY),
+    ),
+    (
+        "format",
+        Operation::new(
+            "str::format",
+            format,
+            null_registrar,
+            Signature::new(Arity::Variadic { required: 1 }, &[STRING, &[]]),
+        ),
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /**
+     * Applies the string operation with the given name
+     */
+    fn evaluate(name: &str, operands: &[Value]) -> ValueResult {
+        let operation = &OPERATIONS.iter().find(|&&(n, _)| n == name).unwrap().1;
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let operands = operands.iter().cloned().map(Ok).collect::<Vec<_>>();
+        match operation.evaluate(&context, &operands) {
+            Total(result) => result,
+            _ => panic!("{} didn't finish", name),
+        }
+    }
+
+    fn value(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap().to_string()
+    }
+
+    fn error(name: &str, operands: &[Value]) -> String {
+        evaluate(name, operands).unwrap_err().cause().to_string()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn lengths_and_indices_count_chars() {
+        let text = || string("héllo→");
+        let index = |i: i64| Value::Integer(i);
+        assert_eq!(value("len", &[text()]), "6");
+        assert_eq!(
+            value("substring", &[text(), index(1), index(6)]),
+            "\"éllo→\""
+        );
+        assert_eq!(value("find", &[text(), string("→")]), "5");
+        assert_eq!(value("find", &[text(), string("x")]), "-1");
+        assert_eq!(
+            error("substring", &[text(), index(2), index(7)]),
+            "index 7 is out of range for a length of 6"
+        );
+        assert!(evaluate("substring", &[text(), index(-1), index(2)]).is_err());
+    }
+
+    #[test]
+    fn string_operations() {
+        assert_eq!(value("concat", &[string("a"), string("bc")]), "\"abc\"");
+        assert_eq!(value("upper", &[string("ßa")]), "\"SSA\"");
+        assert_eq!(value("lower", &[string("ÀB")]), "\"àb\"");
+        assert_eq!(value("trim", &[string(" \t a b\n")]), "\"a b\"");
+        assert_eq!(
+            value("split", &[string("a,b,,c"), string(",")]),
+            "[\"a\", \"b\", \"\", \"c\"]"
+        );
+        assert_eq!(
+            value("split", &[string("hé"), string("")]),
+            "[\"h\", \"é\"]"
+        );
+        assert_eq!(value("starts_with", &[string("abc"), string("ab")]), "true");
+        assert_eq!(
+            value("replace", &[string("a-b-c"), string("-"), string("+")]),
+            "\"a+b+c\""
+        );
+        let list = Value::List(Rc::new(vec![string("a"), string("b")]));
+        assert_eq!(value("join", &[list, string(", ")]), "\"a, b\"");
+    }
+
+    #[test]
+    fn format_fills_placeholders() {
+        assert_eq!(
+            value(
+                "format",
+                &[
+                    string("{} + {} = {{{}}}"),
+                    string("a"),
+                    Value::Integer(2),
+                    Value::Boolean(true)
+                ]
+            ),
+            "\"a + 2 = {true}\""
+        );
+        assert_eq!(
+            error("format", &[string("{} {}"), Value::Integer(1)]),
+            "format string has 2 placeholders but 1 values were given"
+        );
+    }
+}
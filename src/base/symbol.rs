@@ -1,10 +1,22 @@
+use std::cmp;
 use std::collections::HashMap;
 
-use base::value::Value;
+use base::source::Span;
+use base::value::ValueResult;
+
+/**
+ * A symbol's value, together with where it was defined
+ */
+#[derive(Clone, Debug)]
+pub struct SymbolEntry {
+    pub value: ValueResult,
+    /// The span of the form that defined the symbol (if known)
+    pub span: Option<Span>,
+}
 
 #[derive(Debug, Default)]
 pub struct SymbolTable {
-    entries: HashMap<Box<str>, Value>,
+    entries: HashMap<Box<str>, SymbolEntry>,
     finalized: bool,
 }
 
@@ -16,18 +28,37 @@ impl SymbolTable {
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<&Value> {
+    pub fn get(&self, name: &str) -> Option<&ValueResult> {
+        self.entries.get(name).map(|entry| &entry.value)
+    }
+
+    pub fn get_entry(&self, name: &str) -> Option<&SymbolEntry> {
         self.entries.get(name)
     }
 
-    pub fn insert(&mut self, name: &str, value: Value) -> Result<(), Value> {
-        if let Some(old_val) = self.entries.get(name) {
-            return Err(old_val.clone());
+    /**
+     * Adds a symbol to the table
+     *
+     * If the name already exists, the existing entry is returned as an error.
+     */
+    pub fn insert(
+        &mut self,
+        name: &str,
+        value: ValueResult,
+        span: Option<Span>,
+    ) -> Result<(), SymbolEntry> {
+        if let Some(old_entry) = self.entries.get(name) {
+            return Err(old_entry.clone());
         }
-        self.entries.insert(Box::from(name), value);
+        self.entries
+            .insert(Box::from(name), SymbolEntry { value, span });
         Ok(())
     }
 
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| &**name)
+    }
+
     pub fn finalized(&self) -> bool {
         self.finalized
     }
@@ -36,3 +67,50 @@ impl SymbolTable {
         self.finalized = true
     }
 }
+
+/// The maximum number of suggestions returned by `similar_names()`
+const MAX_SUGGESTIONS: usize = 3;
+
+/**
+ * Finds the candidates that are close enough to `name` to be likely misspellings of it
+ *
+ * The results are ordered from closest to furthest (and alphabetically among equally close
+ * names).
+ */
+pub fn similar_names<'a, I: IntoIterator<Item = &'a str>>(
+    name: &str,
+    candidates: I,
+) -> Vec<String> {
+    let max_distance = cmp::max(1, name.chars().count().div_ceil(3));
+    let mut matches = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.dedup();
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_owned())
+        .collect()
+}
+
+/**
+ * Computes the Levenshtein distance between two strings (in chars)
+ */
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b_chars.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b_chars.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + if a_char == b_char { 0 } else { 1 };
+            current[j + 1] = cmp::min(substitution, cmp::min(previous[j + 1], current[j]) + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b_chars.len()]
+}
@@ -1,19 +1,147 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
-use std::rc::Weak;
+use std::rc::{Rc, Weak};
 
+use base::arithmetic;
+use base::bigint::BigInt;
 use base::context::{EvaluationContext, LookupResult};
+use base::control;
+use base::convert;
+use base::diagnostic::Diagnostic;
 use base::expression;
-use base::expression::EvaluationResult;
 use base::expression::EvaluationResult::{Pending, Total};
+use base::expression::{Arity, EvaluationResult};
+use base::function;
+use base::function::Function;
+use base::list;
+use base::logic;
+use base::map;
+use base::map::Map;
+use base::source;
+use base::source::{SourceLocation, SourceRegistry, Span};
+use base::string;
+use base::symbol::SymbolEntry;
 
 // A temporary value type (will later be replaced with something more generic)
 #[derive(Clone, Debug)]
 pub enum Value {
-    Integer(usize),
+    Integer(i64),
+    /// An integer that doesn't fit in an `i64` (see `Value::from_bigint()`)
+    BigInt(BigInt),
+    /// A 64-bit floating point number (which has no literal syntax; see `parse_float`)
+    Float(f64),
     String(String),
+    Boolean(bool),
+    /// An immutable list (which is shared between copies)
+    List(Rc<Vec<Value>>),
+    /// An immutable map (which is shared between copies)
+    Map(Rc<Map>),
+    Function(Rc<Function>),
+}
+
+impl Value {
+    pub fn kind(&self) -> ValueKind {
+        match *self {
+            Value::Integer(_) | Value::BigInt(_) => ValueKind::Integer,
+            Value::Float(_) => ValueKind::Float,
+            Value::String(_) => ValueKind::String,
+            Value::Boolean(_) => ValueKind::Boolean,
+            Value::List(_) => ValueKind::List,
+            Value::Map(_) => ValueKind::Map,
+            Value::Function(_) => ValueKind::Function,
+        }
+    }
+
+    /**
+     * Converts a big integer to a Value, using `Value::Integer` if it fits
+     *
+     * Integers are always stored in the smallest representation that fits them, so a
+     * `Value::BigInt` is never in the range of an `i64`.
+     */
+    pub fn from_bigint(value: BigInt) -> Value {
+        match value.to_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::BigInt(value),
+        }
+    }
+
+    /**
+     * Parses an integer literal (see `BigInt::parse()`)
+     */
+    pub fn parse_integer(text: &str) -> Option<Value> {
+        match text.parse::<i64>() {
+            Ok(value) => Some(Value::Integer(value)),
+            Err(_) => BigInt::parse(text).map(Value::from_bigint),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Value::Integer(value) => write!(formatter, "{}", value),
+            Value::BigInt(ref value) => write!(formatter, "{}", value),
+            // Unlike `Display`, `Debug` always includes a decimal point or an exponent (so floats
+            // can't be mistaken for integers) and prints the shortest text that parses back to
+            // the same value.
+            Value::Float(value) => write!(formatter, "{:?}", value),
+            Value::String(ref value) => write!(formatter, "\"{}\"", value),
+            Value::Boolean(value) => write!(formatter, "{}", value),
+            Value::List(ref elements) => {
+                formatter.write_str("[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        formatter.write_str(", ")?;
+                    }
+                    write!(formatter, "{}", element)?;
+                }
+                formatter.write_str("]")
+            }
+            Value::Map(ref map) => {
+                formatter.write_str("{")?;
+                for (index, (key, value)) in map.iter().enumerate() {
+                    if index > 0 {
+                        formatter.write_str(", ")?;
+                    }
+                    write!(formatter, "{}: {}", key, value)?;
+                }
+                formatter.write_str("}")
+            }
+            Value::Function(ref function) => write!(formatter, "{}", function),
+        }
+    }
+}
+
+/**
+ * Identifies a variant of `Value` (used in operation signatures)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValueKind {
+    Integer,
+    Float,
+    String,
+    Boolean,
+    List,
+    Map,
+    Function,
+}
+
+impl Display for ValueKind {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        let name = match *self {
+            ValueKind::Integer => "integer",
+            ValueKind::Float => "float",
+            ValueKind::String => "string",
+            ValueKind::Boolean => "boolean",
+            ValueKind::List => "list",
+            ValueKind::Map => "map",
+            ValueKind::Function => "function",
+        };
+        formatter.write_str(name)
+    }
 }
 
 /**
@@ -21,37 +149,446 @@ pub enum Value {
  */
 pub type ValueResult = Result<Value, ValueError>;
 
-impl expression::Value for ValueResult {}
+impl expression::Value for ValueResult {
+    type Kind = ValueKind;
+
+    fn kind(&self) -> Option<ValueKind> {
+        match *self {
+            Ok(ref value) => Some(value.kind()),
+            Err(_) => None,
+        }
+    }
+}
+
+/// The maximum number of chars of each operand that a `ValueError` keeps
+pub const MAX_OPERAND_TEXT_LENGTH: usize = 32;
 
 /**
  * An error triggered during evaluation
+ *
+ * Apart from the cause, the error records the context in which it occurred: the form and
+ * operation that failed and the values of the operation's operands. Operations don't need to
+ * fill this in themselves; `evaluate_values()` adds it from the `EvaluationContext`.
  */
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct ValueError {
-    // TODO: include location and/or operator context.
-    //location: SourceLocation
     cause: ValueErrorCause,
+    /*
+     * Boxed so that `ValueResult`s stay small; most errors are created without any context and
+     * are passed around far more often than they are reported.
+     */
+    context: Option<Box<ErrorContext>>,
+}
+
+/**
+ * Where a `ValueError` occurred and the path that it took afterward
+ */
+#[derive(Clone, Debug, Default)]
+struct ErrorContext {
+    /// The span of the form that failed
+    span: Option<Span>,
+    operation: Option<Cow<'static, str>>,
+    /// The operands' values, truncated to `MAX_OPERAND_TEXT_LENGTH` chars
+    operands: Vec<String>,
+    /// The index of the operand that caused the error (if any single operand did)
+    operand_index: Option<usize>,
+    /// The path that the error took after it occurred, innermost first
+    provenance: Vec<ProvenanceFrame>,
 }
 
 impl ValueError {
     pub fn new(cause: ValueErrorCause) -> ValueError {
-        ValueError { cause }
+        ValueError {
+            cause,
+            context: None,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        self.context.get_or_insert_with(Box::default)
+    }
+
+    /**
+     * Marks the operand at the given index as the one responsible for the error
+     */
+    pub fn with_operand_index(mut self, index: usize) -> ValueError {
+        self.context_mut().operand_index = Some(index);
+        self
+    }
+
+    /**
+     * Fills in any context that the error doesn't already have
+     *
+     * `operands` are the values that the failing operation was applied to.
+     */
+    pub fn with_context(mut self, context: &EvaluationContext, operands: &[Value]) -> ValueError {
+        if context.form_span().is_none() && context.operation().is_none() && operands.is_empty() {
+            return self;
+        }
+        let error_context = self.context_mut();
+        if error_context.span.is_none() {
+            error_context.span = context.form_span();
+        }
+        if error_context.operation.is_none() {
+            error_context.operation = context.operation_name();
+        }
+        if error_context.operands.is_empty() {
+            error_context.operands = operands.iter().map(operand_text).collect();
+        }
+        self
+    }
+
+    /**
+     * Records that the error passed through an operand of the form being evaluated in `context`
+     */
+    pub fn through_form(mut self, context: &EvaluationContext) -> ValueError {
+        if context.form_span().is_some() || context.operation().is_some() {
+            self.context_mut().provenance.push(ProvenanceFrame::Form {
+                span: context.form_span(),
+                operation: context.operation_name(),
+            });
+        }
+        self
+    }
+
+    /**
+     * Records that the error was stored in a symbol
+     *
+     * `span` is the location of the symbol's definition.
+     */
+    pub fn through_symbol(mut self, name: &str, span: Option<Span>) -> ValueError {
+        self.context_mut().provenance.push(ProvenanceFrame::Symbol {
+            name: name.to_owned(),
+            span,
+        });
+        self
+    }
+
+    pub fn cause(&self) -> &ValueErrorCause {
+        &self.cause
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.context.as_ref().and_then(|context| context.span)
+    }
+
+    /**
+     * Returns the name of the operation that failed
+     */
+    pub fn operation(&self) -> Option<&str> {
+        self.context
+            .as_ref()
+            .and_then(|context| context.operation.as_deref())
+    }
+
+    /**
+     * Returns the (possibly truncated) text of the failing operation's operands
+     */
+    pub fn operands(&self) -> &[String] {
+        match self.context {
+            Some(ref context) => &context.operands,
+            None => &[],
+        }
+    }
+
+    pub fn operand_index(&self) -> Option<usize> {
+        self.context
+            .as_ref()
+            .and_then(|context| context.operand_index)
+    }
+
+    /**
+     * Returns the forms and symbols that the error passed through, starting from where it occurred
+     */
+    pub fn provenance(&self) -> &[ProvenanceFrame] {
+        match self.context {
+            Some(ref context) => &context.provenance,
+            None => &[],
+        }
+    }
+
+    /**
+     * Renders the error's provenance chain in the style of a backtrace
+     *
+     * Frame 0 describes the error itself and the form where it occurred. `registry` is used to resolve the frames'
+     * locations; frames whose sources can't be found are shown without locations.
+     */
+    pub fn backtrace<R: SourceRegistry + ?Sized>(&self, registry: &R) -> String {
+        let mut out = String::new();
+        let frames = self
+            .provenance()
+            .iter()
+            .map(|frame| (frame.to_string(), frame.span()));
+        let origin = Some((self.to_string(), self.span()));
+        for (i, (description, span)) in origin.into_iter().chain(frames).enumerate() {
+            out.push_str(&format!("{:>4}: {}\n", i, description));
+            if let Some(location) = span.and_then(|span| registry.resolve(span)) {
+                out.push_str(&format!("          at {}\n", location));
+            }
+        }
+        out
+    }
+
+    /**
+     * Returns the errors at the leaves of the error tree
+     *
+     * For an aggregate error, these are the errors that ultimately caused it; any other error
+     * is its own root cause.
+     */
+    pub fn root_causes(&self) -> Vec<&ValueError> {
+        let mut causes = vec![];
+        self.collect_root_causes(&mut causes);
+        causes
+    }
+
+    fn collect_root_causes<'a>(&'a self, causes: &mut Vec<&'a ValueError>) {
+        match self.cause {
+            ValueErrorCause::Multiple(ref errors) => {
+                for error in errors.iter() {
+                    error.collect_root_causes(causes);
+                }
+            }
+            _ => causes.push(self),
+        }
+    }
+}
+
+/**
+ * A step in the path that a `ValueError` took through a program
+ */
+#[derive(Clone, Debug)]
+pub enum ProvenanceFrame {
+    /// The error was an operand of a form, which failed as a result
+    Form {
+        span: Option<Span>,
+        operation: Option<Cow<'static, str>>,
+    },
+    /// The error was stored in a symbol and read back out of it
+    Symbol { name: String, span: Option<Span> },
+}
+
+impl ProvenanceFrame {
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            ProvenanceFrame::Form { span, .. } => span,
+            ProvenanceFrame::Symbol { span, .. } => span,
+        }
+    }
+}
+
+impl Display for ProvenanceFrame {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ProvenanceFrame::Form {
+                operation: Some(ref operation),
+                ..
+            } => write!(formatter, "in `{}`", operation),
+            ProvenanceFrame::Form {
+                operation: None, ..
+            } => formatter.write_str("in form"),
+            ProvenanceFrame::Symbol { ref name, .. } => {
+                write!(formatter, "through symbol `{}`", name)
+            }
+        }
+    }
+}
+
+/**
+ * Renders an operand for inclusion in a `ValueError`
+ */
+fn operand_text(value: &Value) -> String {
+    let text = value.to_string();
+    match text.char_indices().nth(MAX_OPERAND_TEXT_LENGTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/**
+ * A `ValueError` together with the location of the expression that produced it
+ */
+#[derive(Clone, Debug)]
+pub struct LocatedValueError {
+    error: ValueError,
+    location: SourceLocation,
+}
+
+impl LocatedValueError {
+    pub fn new(error: ValueError, location: SourceLocation) -> LocatedValueError {
+        LocatedValueError { error, location }
+    }
+
+    pub fn error(&self) -> &ValueError {
+        &self.error
+    }
+
+    pub fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+
+    pub fn cause(&self) -> &ValueErrorCause {
+        self.error.cause()
     }
 }
 
 /**
  * The cause of a `ValueError`
  */
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum ValueErrorCause {
     UnspecifiedError,
-    WrongNumberOfOperandsForOperation { expected: usize, found: usize },
+    WrongNumberOfOperandsForOperation {
+        expected: usize,
+        found: usize,
+    },
     WrongTypesForOperation,
+    /// Several operands failed independently (their errors are kept in operand order)
+    Multiple(Vec<ValueError>),
+    DivisionByZero,
+    /// The result of an integer operation is too large to represent
+    IntegerOverflow,
+    NegativeExponent,
+    /// Two values that have no order were ordered (such as values of different kinds)
+    IncomparableValues {
+        left: ValueKind,
+        right: ValueKind,
+    },
+    /// A float NaN was ordered
+    UnorderedNaN,
+    /// None of a conditional operation's conditions were true
+    NoConditionMet,
+    /// A list or string was indexed outside of its bounds
+    IndexOutOfRange {
+        index: Value,
+        length: usize,
+    },
+    /// A map didn't contain a key
+    MissingKey {
+        key: Value,
+    },
+    /// A value that can't be hashed was used as a map key
+    UnhashableKey {
+        kind: ValueKind,
+    },
+    /// A format string's placeholders didn't match the number of values given to fill them
+    FormatArgumentMismatch {
+        placeholders: usize,
+        arguments: usize,
+    },
+    /// A string couldn't be parsed as a number of the given kind
+    InvalidNumber {
+        text: String,
+        kind: ValueKind,
+    },
+    /// A symbol was defined when it already had a value
+    SymbolRedefined {
+        name: String,
+        previous: Box<ValueResult>,
+        /// The span of the form that first defined the symbol (if known)
+        first_definition: Option<Span>,
+    },
+    /// A symbol was read that isn't defined in any enclosing scope
+    UndefinedSymbol {
+        name: String,
+        /// Defined names that are similar to `name`
+        suggestions: Vec<String>,
+    },
+    /// A function was called inside more nested calls than the limit allows
+    CallDepthExceeded {
+        limit: usize,
+    },
+}
+
+impl ValueErrorCause {
+    /**
+     * Returns the cause's stable diagnostic code (see `base::explain`)
+     */
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ValueErrorCause::UnspecifiedError => "R0100",
+            ValueErrorCause::WrongNumberOfOperandsForOperation { .. } => "R0101",
+            ValueErrorCause::WrongTypesForOperation => "R0102",
+            ValueErrorCause::Multiple(_) => "R0103",
+            ValueErrorCause::SymbolRedefined { .. } => "R0104",
+            ValueErrorCause::UndefinedSymbol { .. } => "R0105",
+            ValueErrorCause::DivisionByZero => "R0106",
+            ValueErrorCause::IntegerOverflow => "R0107",
+            ValueErrorCause::NegativeExponent => "R0108",
+            ValueErrorCause::IncomparableValues { .. } => "R0109",
+            ValueErrorCause::UnorderedNaN => "R0116",
+            ValueErrorCause::NoConditionMet => "R0110",
+            ValueErrorCause::IndexOutOfRange { .. } => "R0111",
+            ValueErrorCause::MissingKey { .. } => "R0112",
+            ValueErrorCause::UnhashableKey { .. } => "R0113",
+            ValueErrorCause::FormatArgumentMismatch { .. } => "R0114",
+            ValueErrorCause::InvalidNumber { .. } => "R0115",
+            ValueErrorCause::CallDepthExceeded { .. } => "R0117",
+        }
+    }
+}
+
+impl Display for ValueErrorCause {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ValueErrorCause::UnspecifiedError => formatter.write_str("unspecified error"),
+            ValueErrorCause::WrongNumberOfOperandsForOperation { expected, found } => write!(
+                formatter,
+                "wrong number of operands (expected {}, found {})",
+                expected, found
+            ),
+            ValueErrorCause::WrongTypesForOperation => {
+                formatter.write_str("wrong operand types for operation")
+            }
+            ValueErrorCause::Multiple(ref errors) => {
+                write!(formatter, "{} operands failed", errors.len())
+            }
+            ValueErrorCause::SymbolRedefined { ref name, .. } => {
+                write!(formatter, "symbol `{}` is already defined", name)
+            }
+            ValueErrorCause::UndefinedSymbol { ref name, .. } => {
+                write!(formatter, "undefined symbol `{}`", name)
+            }
+            ValueErrorCause::DivisionByZero => formatter.write_str("division by zero"),
+            ValueErrorCause::IntegerOverflow => formatter.write_str("integer overflow"),
+            ValueErrorCause::NegativeExponent => formatter.write_str("negative exponent"),
+            ValueErrorCause::IncomparableValues { left, right } => {
+                write!(formatter, "cannot compare {} with {}", left, right)
+            }
+            ValueErrorCause::UnorderedNaN => formatter.write_str("cannot order NaN"),
+            ValueErrorCause::NoConditionMet => formatter.write_str("no condition was met"),
+            ValueErrorCause::IndexOutOfRange { ref index, length } => write!(
+                formatter,
+                "index {} is out of range for a length of {}",
+                index, length
+            ),
+            ValueErrorCause::MissingKey { ref key } => write!(formatter, "missing key {}", key),
+            ValueErrorCause::UnhashableKey { kind } => {
+                write!(formatter, "a {} can't be used as a map key", kind)
+            }
+            ValueErrorCause::FormatArgumentMismatch {
+                placeholders,
+                arguments,
+            } => write!(
+                formatter,
+                "format string has {} placeholders but {} values were given",
+                placeholders, arguments
+            ),
+            ValueErrorCause::InvalidNumber { ref text, kind } => {
+                write!(formatter, "\"{}\" is not a valid {}", text, kind)
+            }
+            ValueErrorCause::CallDepthExceeded { limit } => {
+                write!(formatter, "more than {} nested function calls", limit)
+            }
+        }
+    }
 }
 
 impl Display for ValueError {
     fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(formatter, "Error: {}", self.description())
+        match self.operation() {
+            Some(operation) => write!(formatter, "{} in `{}`", self.cause, operation),
+            None => self.cause.fmt(formatter),
+        }
     }
 }
 
@@ -61,81 +598,311 @@ impl Error for ValueError {
     }
 }
 
+impl From<ValueError> for Diagnostic {
+    fn from(error: ValueError) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(error.to_string()).with_code(error.cause.code());
+        if let Some(span) = error.span() {
+            diagnostic = diagnostic.with_span(span);
+        }
+        if let ValueErrorCause::Multiple(_) = error.cause {
+            for cause in error.root_causes() {
+                diagnostic = match cause.span() {
+                    Some(span) => diagnostic.with_label(span, cause.to_string()),
+                    None => diagnostic.with_note(format!("caused by: {}", cause)),
+                };
+            }
+            return diagnostic;
+        }
+        match error.cause {
+            ValueErrorCause::SymbolRedefined {
+                ref previous,
+                first_definition,
+                ..
+            } => {
+                if let Some(span) = first_definition {
+                    diagnostic = diagnostic.with_label(span, "first defined here".to_owned());
+                }
+                let previous = match **previous {
+                    Ok(ref value) => format!("the previous value was `{}`", operand_text(value)),
+                    Err(ref error) => format!("the previous value was an error: {}", error),
+                };
+                diagnostic = diagnostic.with_note(previous);
+            }
+            ValueErrorCause::UndefinedSymbol {
+                ref suggestions, ..
+            } if !suggestions.is_empty() => {
+                let names = suggestions
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>();
+                diagnostic = diagnostic.with_note(format!("did you mean {}?", names.join(" or ")));
+            }
+            _ => (),
+        }
+        let operands = error.operands();
+        if let Some(index) = error.operand_index() {
+            if let Some(operand) = operands.get(index) {
+                diagnostic = diagnostic.with_note(format!(
+                    "operand {} (`{}`) is the cause",
+                    index + 1,
+                    operand
+                ));
+            }
+        }
+        if !operands.is_empty() {
+            diagnostic =
+                diagnostic.with_note(format!("the operands were: {}", operands.join(", ")));
+        }
+        for (i, frame) in error.provenance().iter().enumerate() {
+            diagnostic = match frame.span() {
+                Some(span) => diagnostic.with_label(span, format!("{}: {}", i + 1, frame)),
+                None => diagnostic.with_note(format!("{}: {}", i + 1, frame)),
+            };
+        }
+        diagnostic
+    }
+}
+
+impl Display for LocatedValueError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(formatter, "{} at {}", self.error, self.location)
+    }
+}
+
+impl Error for LocatedValueError {
+    fn description(&self) -> &str {
+        "Value error"
+    }
+}
+
+impl source::Error for LocatedValueError {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+}
+
+impl From<LocatedValueError> for Diagnostic {
+    fn from(error: LocatedValueError) -> Diagnostic {
+        Diagnostic::from(error.error).with_span(error.location.to_span())
+    }
+}
+
 pub type Operation = expression::Operation<EvaluationContext>;
 pub type EvaluationListener = expression::EvaluationListener<EvaluationContext>;
 pub type OperationGroup = expression::OperationGroup<EvaluationContext>;
 pub type Expression = expression::Expression<EvaluationContext>;
 pub type PartialExpression = expression::PartialExpression<EvaluationContext>;
+pub type Signature = expression::Signature<ValueKind>;
+pub type LazyStep = expression::LazyStep<EvaluationContext>;
+pub type QuotedOperand = expression::QuotedOperand<EvaluationContext>;
 
-fn propagate_errors(
+/**
+ * Combines the errors among an operation's operands into the error that the operation fails with
+ *
+ * Returns None if none of the operands failed.
+ */
+pub fn propagate_errors(
+    context: &EvaluationContext,
+    operands: &[ValueResult],
+) -> Option<ValueError> {
+    let mut errors = operands
+        .iter()
+        .filter_map(|operand| operand.as_ref().err().cloned())
+        .collect::<Vec<_>>();
+    match errors.len() {
+        0 => None,
+        1 => Some(errors.pop().unwrap().through_form(context)),
+        _ => {
+            let error = ValueError::new(ValueErrorCause::Multiple(errors));
+            Some(error.with_context(context, &[]))
+        }
+    }
+}
+
+/**
+ * Applies an operation to the values of its operands
+ *
+ * If any of the operands failed, the operation isn't applied, and their errors are propagated
+ * instead. Errors returned by the operation are annotated with the context that it was
+ * evaluated in.
+ */
+pub fn evaluate_values(
     op: fn(&EvaluationContext, &[Value]) -> EvaluationResult<ValueResult>,
     context: &EvaluationContext,
     operands: &[ValueResult],
 ) -> EvaluationResult<ValueResult> {
-    let mut values = Vec::<Value>::with_capacity(operands.len());
-    for operand in operands.iter() {
-        match *operand {
-            Ok(ref val) => values.push(val.clone()),
-            // TODO: combine multiple errors if present.
-            Err(err) => return Total(Err(err)),
-        }
+    if let Some(error) = propagate_errors(context, operands) {
+        return Total(Err(error));
     }
 
-    op(context, values.as_ref())
+    let values = operands
+        .iter()
+        .filter_map(|operand| operand.as_ref().ok().cloned())
+        .collect::<Vec<_>>();
+    match op(context, values.as_ref()) {
+        Total(Err(err)) => Total(Err(err.with_context(context, &values))),
+        result => result,
+    }
 }
 
-fn unary_op(
+/**
+ * Checks that an operation received an acceptable number of operands
+ */
+pub fn check_operand_count(operands: &[Value], arity: Arity) -> Result<(), ValueError> {
+    if arity.accepts(operands.len()) {
+        return Ok(());
+    }
+    Err(wrong_operand_count(arity, operands.len()))
+}
+
+/**
+ * Returns the error for an operation that received `found` operands instead of `arity`
+ */
+pub fn wrong_operand_count(arity: Arity, found: usize) -> ValueError {
+    let expected = match arity {
+        Arity::Fixed(count) => count,
+        Arity::Optional { required, .. } | Arity::Variadic { required } => required,
+    };
+    ValueError::new(ValueErrorCause::WrongNumberOfOperandsForOperation { expected, found })
+}
+
+/**
+ * Applies an operation to its only operand
+ *
+ * The operation's signature must take exactly one operand. `Operation::evaluate()` checks the
+ * operands against the signature first, so the count isn't checked again here.
+ */
+pub fn unary_op(
     op: fn(&EvaluationContext, &Value) -> EvaluationResult<ValueResult>,
     context: &EvaluationContext,
     operands: &[Value],
 ) -> EvaluationResult<ValueResult> {
-    if operands.len() == 1 {
-        op(context, &operands[0])
-    } else {
-        Total(Err(ValueError::new(
-            ValueErrorCause::WrongNumberOfOperandsForOperation {
-                expected: 1,
-                found: operands.len(),
-            },
-        )))
-    }
+    op(context, &operands[0])
 }
 
-fn binary_op(
+/**
+ * Applies an operation to its two operands
+ *
+ * The operation's signature must take exactly two operands.
+ */
+pub fn binary_op(
     op: fn(&EvaluationContext, &Value, &Value) -> EvaluationResult<ValueResult>,
     context: &EvaluationContext,
     operands: &[Value],
 ) -> EvaluationResult<ValueResult> {
-    if operands.len() == 2 {
-        op(context, &operands[0], &operands[1])
-    } else {
-        Total(Err(ValueError::new(
-            ValueErrorCause::WrongNumberOfOperandsForOperation {
-                expected: 2,
-                found: operands.len(),
-            },
-        )))
-    }
+    op(context, &operands[0], &operands[1])
+}
+
+/**
+ * Like `unary_op()`, but for operations that handle errors themselves
+ */
+fn unary_result_op(
+    op: fn(&EvaluationContext, &ValueResult) -> ValueResult,
+    context: &EvaluationContext,
+    operands: &[ValueResult],
+) -> EvaluationResult<ValueResult> {
+    let result = op(context, &operands[0]);
+    Total(annotate_result(result, context, operands))
 }
 
-fn null_registrar(_: &EvaluationContext, _: &Weak<PartialExpression>, _: &[ValueResult]) {}
+/**
+ * Like `binary_op()`, but for operations that handle errors themselves
+ */
+fn binary_result_op(
+    op: fn(&EvaluationContext, &ValueResult, &ValueResult) -> ValueResult,
+    context: &EvaluationContext,
+    operands: &[ValueResult],
+) -> EvaluationResult<ValueResult> {
+    let result = op(context, &operands[0], &operands[1]);
+    Total(annotate_result(result, context, operands))
+}
 
-fn add(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
-    fn do_add(_: &EvaluationContext, a: &Value, b: &Value) -> EvaluationResult<ValueResult> {
-        Total(match *a {
-            Value::Integer(a_num) => match *b {
-                Value::Integer(b_num) => Ok(Value::Integer(a_num + b_num)),
-                _ => Err(ValueError::new(ValueErrorCause::WrongTypesForOperation)),
-            },
-            _ => Err(ValueError::new(ValueErrorCause::WrongTypesForOperation)),
-        })
+/**
+ * Annotates an error returned by an operation that handles errors itself
+ *
+ * Only the operands that succeeded are recorded in the error.
+ */
+fn annotate_result(
+    result: ValueResult,
+    context: &EvaluationContext,
+    operands: &[ValueResult],
+) -> ValueResult {
+    result.map_err(|err| {
+        let values = operands
+            .iter()
+            .filter_map(|operand| operand.as_ref().ok().cloned())
+            .collect::<Vec<_>>();
+        err.with_context(context, &values)
+    })
+}
+
+/**
+ * Returns an operand of a lazy operation, or the step that the operation should take if the
+ * operand isn't available as a value
+ *
+ * If the operand hasn't been evaluated yet, the step asks for it. If it failed, the step
+ * propagates its error in the same way as `evaluate_values()`.
+ */
+pub fn lazy_operand(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    index: usize,
+) -> Result<Value, LazyStep> {
+    match operands[index] {
+        None => Err(LazyStep::Force(index)),
+        Some(Ok(ref value)) => Ok(value.clone()),
+        Some(Err(ref error)) => Err(LazyStep::Total(Err(error.clone().through_form(context)))),
     }
+}
 
-    fn bin_add(context: &EvaluationContext, operands: &[Value]) -> EvaluationResult<ValueResult> {
-        binary_op(do_add, context, operands)
+/**
+ * Like `lazy_operand()`, but fails if the operand isn't a boolean
+ */
+pub fn lazy_condition(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    index: usize,
+) -> Result<bool, LazyStep> {
+    match lazy_operand(context, operands, index)? {
+        Value::Boolean(value) => Ok(value),
+        _ => {
+            let error =
+                ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(index);
+            Err(lazy_error(context, operands, error))
+        }
     }
+}
+
+/**
+ * Fails a lazy operation
+ *
+ * The error is annotated with the context, along with the values of the leading operands that
+ * have been evaluated.
+ */
+pub fn lazy_error(
+    context: &EvaluationContext,
+    operands: &[Option<ValueResult>],
+    error: ValueError,
+) -> LazyStep {
+    let values = operands
+        .iter()
+        .take_while(|operand| operand.as_ref().is_some_and(|result| result.is_ok()))
+        .filter_map(|operand| operand.clone().and_then(|result| result.ok()))
+        .collect::<Vec<_>>();
+    LazyStep::Total(Err(error.with_context(context, &values)))
+}
 
-    propagate_errors(bin_add, context, args)
+pub fn null_registrar(_: &EvaluationContext, _: &Weak<PartialExpression>, _: &[ValueResult]) {}
+
+/**
+ * Builds the error for an attempt to redefine a symbol
+ */
+fn redefinition_error(name: &str, previous: SymbolEntry) -> ValueError {
+    ValueError::new(ValueErrorCause::SymbolRedefined {
+        name: name.to_owned(),
+        previous: Box::new(previous.value),
+        first_definition: previous.span,
+    })
 }
 
 fn define_symbol(
@@ -148,17 +915,13 @@ fn define_symbol(
         value: &Value,
     ) -> EvaluationResult<ValueResult> {
         if let Value::String(ref name_str) = *name {
-            let scope = context.scope();
-            let mut scope_mut = scope.borrow_mut();
-            match scope_mut.define_symbol(name_str, value.clone()) {
+            match context.define_symbol(name_str, Ok(value.clone())) {
                 Ok(_) => Total(Ok(value.clone())),
-                // TODO: do something useful with the error value.
-                Err(_) => Total(Err(ValueError::new(ValueErrorCause::UnspecifiedError))),
+                Err(previous) => Total(Err(redefinition_error(name_str, previous))),
             }
         } else {
-            Total(Err(ValueError::new(
-                ValueErrorCause::WrongTypesForOperation,
-            )))
+            let error = ValueError::new(ValueErrorCause::WrongTypesForOperation);
+            Total(Err(error.with_operand_index(0)))
         }
     }
 
@@ -169,27 +932,36 @@ fn define_symbol(
         binary_op(do_define, context, operands)
     }
 
-    propagate_errors(bin_define, context, args)
+    // DEFINE_OP handles errors itself so that a failed value can still be stored in the symbol,
+    // where its readers will see where it came from.
+    if let (Some(Ok(Value::String(name))), Some(Err(error))) = (args.first(), args.get(1)) {
+        let error = error.clone().through_symbol(name, context.form_span());
+        if let Err(previous) = context.define_symbol(name, Err(error)) {
+            let error = redefinition_error(name, previous);
+            return Total(Err(error.with_context(context, &[])));
+        }
+    }
+
+    evaluate_values(bin_define, context, args)
 }
 
 fn get_symbol(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
     fn do_get(context: &EvaluationContext, name: &Value) -> EvaluationResult<ValueResult> {
         if let Value::String(ref name_str) = *name {
             let scope = context.scope();
-            let mut scope_mut = scope.borrow_mut();
+            let scope_ref = scope.borrow();
             // TODO: handle registration.
-            match scope_mut.get_symbol(name_str) {
-                LookupResult::Total(value) => Total(Ok(value.clone())),
+            match scope_ref.get_symbol(name_str) {
+                LookupResult::Total(value) => Total(value),
                 LookupResult::Pending => Pending,
-                // TODO: do something useful with the error value.
                 LookupResult::NotFound => {
-                    Total(Err(ValueError::new(ValueErrorCause::UnspecifiedError)))
+                    let error = scope_ref.undefined_symbol_error(name_str);
+                    Total(Err(error.with_operand_index(0)))
                 }
             }
         } else {
-            Total(Err(ValueError::new(
-                ValueErrorCause::WrongTypesForOperation,
-            )))
+            let error = ValueError::new(ValueErrorCause::WrongTypesForOperation);
+            Total(Err(error.with_operand_index(0)))
         }
     }
 
@@ -197,7 +969,7 @@ fn get_symbol(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationRe
         unary_op(do_get, context, operands)
     }
 
-    propagate_errors(unary_get, context, args)
+    evaluate_values(unary_get, context, args)
 }
 
 fn get_symbol_register(
@@ -215,22 +987,240 @@ fn get_symbol_register(
     }
 }
 
-const ADD_OP: Operation = Operation::new("add", add, null_registrar);
-const DEFINE_OP: Operation = Operation::new("define_symbol", define_symbol, null_registrar);
-const GET_SYM_OP: Operation = Operation::new("get_symbol", get_symbol, get_symbol_register);
+/**
+ * Returns a map describing how its operand turned out
+ *
+ * The map is `{"ok": true, "value": value}` if the operand succeeded and
+ * `{"ok": false, "error": message}` if it failed, so a caught error can't be mistaken for a
+ * value.
+ */
+fn try_value(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_try(_: &EvaluationContext, value: &ValueResult) -> ValueResult {
+        let (ok, key, value) = match *value {
+            Ok(ref value) => (true, "value", value.clone()),
+            Err(ref error) => (false, "error", Value::String(error.to_string())),
+        };
+        let mut outcome = Map::new();
+        // String and boolean keys are always hashable.
+        outcome
+            .insert(Value::String("ok".to_owned()), Value::Boolean(ok))
+            .unwrap();
+        outcome
+            .insert(Value::String(key.to_owned()), value)
+            .unwrap();
+        Ok(Value::Map(Rc::new(outcome)))
+    }
+
+    unary_result_op(do_try, context, args)
+}
+
+/**
+ * Returns its first operand if it succeeded, or its second operand otherwise
+ */
+fn or_else(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_or_else(
+        _: &EvaluationContext,
+        value: &ValueResult,
+        default: &ValueResult,
+    ) -> ValueResult {
+        match *value {
+            Ok(_) => value.clone(),
+            Err(_) => default.clone(),
+        }
+    }
+
+    binary_result_op(do_or_else, context, args)
+}
+
+/**
+ * Returns true if its operand failed
+ */
+fn is_error(context: &EvaluationContext, args: &[ValueResult]) -> EvaluationResult<ValueResult> {
+    fn do_is_error(_: &EvaluationContext, value: &ValueResult) -> ValueResult {
+        Ok(Value::Boolean(value.is_err()))
+    }
+
+    unary_result_op(do_is_error, context, args)
+}
+
+/**
+ * Returns the message of a failed operand
+ *
+ * Fails if the operand succeeded.
+ */
+fn error_message(
+    context: &EvaluationContext,
+    args: &[ValueResult],
+) -> EvaluationResult<ValueResult> {
+    fn do_error_message(_: &EvaluationContext, value: &ValueResult) -> ValueResult {
+        match *value {
+            Ok(_) => {
+                Err(ValueError::new(ValueErrorCause::WrongTypesForOperation).with_operand_index(0))
+            }
+            Err(ref error) => Ok(Value::String(error.to_string())),
+        }
+    }
+
+    unary_result_op(do_error_message, context, args)
+}
+
+/// The `define_symbol` operation, whose literal names the reader looks for when it reads a call
+/// to an undefined operation
+pub const DEFINE_OP: Operation = Operation::new(
+    "define_symbol",
+    define_symbol,
+    null_registrar,
+    Signature::new(Arity::Fixed(2), &[&[ValueKind::String], &[]]),
+)
+.handling_errors();
+/// The `get_symbol` operation, which the reader also uses for parameters written as bare symbols
+pub const GET_SYM_OP: Operation = Operation::new(
+    "get_symbol",
+    get_symbol,
+    get_symbol_register,
+    Signature::new(Arity::Fixed(1), &[&[ValueKind::String]]),
+);
+const TRY_OP: Operation = Operation::new(
+    "try",
+    try_value,
+    null_registrar,
+    Signature::any(Arity::Fixed(1)),
+)
+.handling_errors();
+const OR_ELSE_OP: Operation = Operation::new(
+    "or_else",
+    or_else,
+    null_registrar,
+    Signature::any(Arity::Fixed(2)),
+)
+.handling_errors();
+const IS_ERROR_OP: Operation = Operation::new(
+    "is_error",
+    is_error,
+    null_registrar,
+    Signature::any(Arity::Fixed(1)),
+)
+.handling_errors();
+const ERROR_MESSAGE_OP: Operation = Operation::new(
+    "error_message",
+    error_message,
+    null_registrar,
+    Signature::any(Arity::Fixed(1)),
+)
+.handling_errors();
 
 /**
  * Returns the default Rhodium `OperationGroup`
  */
 pub fn default_operations() -> OperationGroup {
-    OperationGroup::new(
+    let mut group = OperationGroup::new(
         [
-            ("add", ADD_OP),
             ("define_symbol", DEFINE_OP),
             ("get_symbol", GET_SYM_OP),
+            ("try", TRY_OP),
+            ("or_else", OR_ELSE_OP),
+            ("is_error", IS_ERROR_OP),
+            ("error_message", ERROR_MESSAGE_OP),
         ].iter()
+            .chain(arithmetic::OPERATIONS.iter())
+            .chain(logic::OPERATIONS.iter())
+            .chain(control::OPERATIONS.iter())
+            .chain(list::OPERATIONS.iter())
+            .chain(map::OPERATIONS.iter())
+            .chain(convert::OPERATIONS.iter())
+            .chain(function::OPERATIONS.iter())
             .cloned()
             .map(|i| (Box::<str>::from(i.0), i.1))
             .collect(),
-    )
+    );
+    group.insert_namespace(
+        "str",
+        OperationGroup::new(
+            string::OPERATIONS
+                .iter()
+                .cloned()
+                .map(|i| (Box::<str>::from(i.0), i.1))
+                .collect(),
+        ),
+    );
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::Scope;
+    use std::cell::RefCell;
+
+    fn context() -> EvaluationContext {
+        EvaluationContext::new(Rc::new(RefCell::new(Scope::new())))
+    }
+
+    fn operation(name: &str) -> Operation {
+        default_operations().get(name).unwrap().clone()
+    }
+
+    fn integers(values: &[i64]) -> Vec<Expression> {
+        values
+            .iter()
+            .map(|&value| Expression::from_value(Ok(Value::Integer(value))))
+            .collect()
+    }
+
+    fn failure(expression: Expression) -> ValueError {
+        match expression {
+            Expression::Total(Err(error)) => error,
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn operations_built_directly_check_their_operand_count() {
+        let context = context();
+        let error = failure(Expression::from_op(
+            operation("add"),
+            &context,
+            integers(&[1]),
+        ));
+        match *error.cause() {
+            ValueErrorCause::WrongNumberOfOperandsForOperation { expected, found } => {
+                assert_eq!((expected, found), (2, 1));
+            }
+            ref cause => panic!("unexpected cause {:?}", cause),
+        }
+        assert_eq!(error.operation(), None);
+
+        let error = failure(Expression::from_op(operation("not"), &context, vec![]));
+        assert!(matches!(
+            *error.cause(),
+            ValueErrorCause::WrongNumberOfOperandsForOperation { found: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn operations_built_directly_check_their_operand_kinds() {
+        let operands = vec![
+            Expression::from_value(Ok(Value::Integer(1))),
+            Expression::from_value(Ok(Value::String("a".to_owned()))),
+        ];
+        let error = failure(Expression::from_op(operation("add"), &context(), operands));
+        assert!(matches!(
+            *error.cause(),
+            ValueErrorCause::WrongTypesForOperation
+        ));
+        assert_eq!(error.operand_index(), Some(1));
+    }
+
+    #[test]
+    fn lazy_operations_built_directly_check_their_operand_count() {
+        let error = failure(Expression::from_lazy_op(
+            operation("if"),
+            &context(),
+            vec![],
+        ));
+        assert!(matches!(
+            *error.cause(),
+            ValueErrorCause::WrongNumberOfOperandsForOperation { found: 0, .. }
+        ));
+    }
 }
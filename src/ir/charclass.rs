@@ -22,3 +22,21 @@ pub fn is_identifier(c: &char) -> bool {
 pub fn is_decimal_digit(c: &char) -> bool {
     c.is_digit(10)
 }
+
+pub fn is_hex_digit(c: &char) -> bool {
+    c.is_ascii_hexdigit()
+}
+
+/**
+ * Returns the length of the namespace separator (`::` or `.`) at the start of `s`, or 0 if
+ * there isn't one
+ */
+pub fn namespace_separator_length(s: &str) -> usize {
+    if s.starts_with("::") {
+        "::".len()
+    } else if s.starts_with('.') {
+        ".".len()
+    } else {
+        0
+    }
+}
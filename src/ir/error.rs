@@ -0,0 +1,174 @@
+use std::error;
+use std::fmt;
+use std::fmt::Display;
+
+use base::diagnostic::Diagnostic;
+use base::source;
+use base::source::SourceLocation;
+use base::value::{LocatedValueError, ValueErrorCause};
+use ir::lexer::LexicalError;
+use ir::parser::{ParseError, ParseErrorCause};
+
+/**
+ * Any located error that can occur while reading or evaluating the level-1 IR
+ */
+#[derive(Clone, Debug)]
+pub enum Error {
+    Lexical(LexicalError),
+    Parse(ParseError),
+    Value(LocatedValueError),
+}
+
+/**
+ * The cause of an `Error`
+ */
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorCause<'a> {
+    Lexical,
+    Parse(&'a ParseErrorCause),
+    Value(&'a ValueErrorCause),
+}
+
+impl Error {
+    pub fn location(&self) -> &SourceLocation {
+        match *self {
+            Error::Lexical(ref error) => &error.location,
+            Error::Parse(ref error) => error.location(),
+            Error::Value(ref error) => error.location(),
+        }
+    }
+
+    pub fn cause<'a>(&'a self) -> ErrorCause<'a> {
+        match *self {
+            Error::Lexical(_) => ErrorCause::Lexical,
+            Error::Parse(ref error) => ErrorCause::Parse(error.cause()),
+            Error::Value(ref error) => ErrorCause::Value(error.cause()),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Lexical(ref error) => error.fmt(formatter),
+            Error::Parse(ref error) => error.fmt(formatter),
+            Error::Value(ref error) => error.fmt(formatter),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Lexical(_) => "unexpected token",
+            Error::Parse(_) => "parsing error",
+            Error::Value(_) => "Value error",
+        }
+    }
+}
+
+impl source::Error for Error {
+    fn location(&self) -> &SourceLocation {
+        Error::location(self)
+    }
+}
+
+impl From<LexicalError> for Error {
+    fn from(error: LexicalError) -> Error {
+        Error::Lexical(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+impl From<LocatedValueError> for Error {
+    fn from(error: LocatedValueError) -> Error {
+        Error::Value(error)
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Diagnostic {
+        match error {
+            Error::Lexical(error) => error.into(),
+            Error::Parse(error) => error.into(),
+            Error::Value(error) => error.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::context::{EvaluationContext, Scope};
+    use base::source::SourceText;
+    use base::value::default_operations;
+    use ir::expression::located_expression_from_parser;
+    use ir::lexer::Lexer;
+    use ir::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /**
+     * Returns the first error that reading and evaluating `text` produces
+     */
+    fn first_error(text: &str) -> Error {
+        let source = Rc::new(SourceText::with_name("a.rh", text.to_owned()).unwrap());
+        let mut parser = Parser::new(Lexer::new(source));
+        let mut operations = default_operations();
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        loop {
+            match located_expression_from_parser(&mut parser, &mut operations, &context) {
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return error,
+                None => panic!("no error in {:?}", text),
+            }
+        }
+    }
+
+    /**
+     * Checks an error through the `source::Error` trait
+     */
+    fn location_text<E: source::Error>(error: &E) -> String {
+        format!("{} {:?}", error.location(), error.location().text())
+    }
+
+    #[test]
+    fn lexical_errors_are_located() {
+        let error = first_error("(add 1 2)\n  $");
+        assert!(matches!(
+            error.cause(),
+            ErrorCause::Parse(&ParseErrorCause::Lexical)
+        ));
+        assert_eq!(location_text(&error), "a.rh:2:3 \"\"");
+        assert_eq!(Diagnostic::from(error).code, Some("R0001"));
+    }
+
+    #[test]
+    fn parse_errors_are_located() {
+        let error = first_error("(add 1 2)\n(1 2)");
+        assert!(matches!(
+            error.cause(),
+            ErrorCause::Parse(&ParseErrorCause::MissingOperation)
+        ));
+        assert_eq!(location_text(&error), "a.rh:2:2 \"1\"");
+        assert_eq!(Diagnostic::from(error).code, Some("R0005"));
+    }
+
+    #[test]
+    fn value_errors_are_located_at_their_form() {
+        let error = first_error("(add 1 2) (div 1\n  0)");
+        assert!(matches!(
+            error.cause(),
+            ErrorCause::Value(&ValueErrorCause::DivisionByZero)
+        ));
+        assert_eq!(location_text(&error), "a.rh:1:11 \"(div 1\\n  0)\"");
+        let diagnostic = Diagnostic::from(error);
+        assert_eq!(diagnostic.code, Some("R0106"));
+        assert!(diagnostic.span.is_some());
+    }
+}
@@ -1,61 +1,1203 @@
-use base::context::EvaluationContext;
-use base::value::{Expression, OperationGroup, Value};
+use base::context::{EvaluationContext, Scope};
+use base::expression;
+use base::expression::{canonical_name, Quoted, SignatureMismatch, NAMESPACE_SEPARATOR};
+use base::function;
+use base::function::{Function, FunctionBody, APPLY_OP};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use base::source::{SourceLocation, SourceText, Span};
+use base::value::{Expression, Operation, OperationGroup, QuotedOperand, Value, ValueKind};
+use base::value::{LocatedValueError, ValueError, ValueErrorCause, ValueResult};
+use base::value::{DEFINE_OP, GET_SYM_OP};
 use ir;
+use ir::lexer;
+use ir::lexer::TokenType;
 use ir::parser::{Element, ParseError, ParseErrorCause};
 
+/// The name of the directive that brings a namespace into scope
+pub const USE_DIRECTIVE: &str = "use";
+
+/**
+ * The name of the form that creates a function
+ *
+ * `(lambda (x y) body)` creates a function of `x` and `y` (see `base::function`), and
+ * `(lambda () body)` creates one without parameters. The body isn't evaluated until the function
+ * is called. Within the body, the parameters can be written as bare symbols.
+ */
+pub const LAMBDA_FORM: &str = "lambda";
+
+/**
+ * The name of the form that defines a named operation
+ *
+ * `(defop square (x) (mul x x))` stores a function of `x` in the symbol `square` (as
+ * `define_symbol` and `lambda` would) and adds a `square` operation to the active operation
+ * group, so later forms can call `(square 5)`. Within the body, the parameters are written as
+ * bare symbols. `defop` is only allowed at the top level, and its value is the function.
+ *
+ * A call to an undefined operation whose name has no namespace calls the function stored in the
+ * symbol of the same name instead, so a call that is read before its `defop` stays pending until
+ * the `defop` is evaluated. This also lets a body call its own operation. The call is reported
+ * as an undefined operation as soon as it's read if no `defop` form in the source has the same
+ * name, no `define_symbol` form in the source has the same literal name and the symbol isn't
+ * defined yet. Otherwise, it's reported if the input ends without defining the operation (or a
+ * symbol of the same name).
+ */
+pub const DEFOP_FORM: &str = "defop";
+
+/**
+ * Reads the next expression from a parser
+ *
+ * Top-level directives (such as `(use "math")`) are applied to `operations` as they are
+ * encountered and do not produce expressions of their own. `defop` forms add their operations
+ * to `operations` as they are evaluated. Once the parser runs out of elements, each call to an
+ * operation that is still undefined is returned as an `UndefinedOperation` error, and then the
+ * context's scope is finalized, so forms that are still waiting for undefined symbols fail with
+ * `UndefinedSymbol` errors.
+ */
 pub fn expression_from_parser(
     parser: &mut ir::Parser,
-    operations: &OperationGroup,
+    operations: &mut OperationGroup,
     context: &EvaluationContext,
 ) -> Option<Result<Expression, ParseError>> {
-    match parser.next_element() {
-        Some(result) => Some(match result {
-            Ok(element) => expression_from_element(element, operations, context),
-            Err(error) => Err(error),
-        }),
-        None => None,
+    next_expression(parser, operations, context).map(|result| result.map(|(_, e)| e))
+}
+
+/**
+ * Like `expression_from_parser()`, but reports a form that evaluates to an error as an
+ * `ir::Error` located at the form
+ *
+ * The expression is never `Total(Err(..))`. A partial expression may still fail later; that
+ * error has no location, apart from the span recorded in its `ValueError`.
+ */
+pub fn located_expression_from_parser(
+    parser: &mut ir::Parser,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+) -> Option<Result<Expression, ir::Error>> {
+    let source = Rc::clone(parser.source());
+    next_expression(parser, operations, context).map(|result| {
+        let (span, expression) = result?;
+        match expression {
+            Expression::Total(Err(error)) => {
+                let location = SourceLocation::from_span(source, span);
+                Err(LocatedValueError::new(error, location).into())
+            }
+            expression => Ok(expression),
+        }
+    })
+}
+
+/**
+ * Reads the next expression from a parser along with the span of the form it came from
+ */
+fn next_expression(
+    parser: &mut ir::Parser,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+) -> Option<Result<(Span, Expression), ParseError>> {
+    let source = Rc::clone(parser.source());
+    loop {
+        let mut element = match parser.next_element() {
+            Some(Ok(element)) => element,
+            Some(Err(error)) => return Some(Err(error)),
+            None => {
+                let error = undefined_call_error(&source, operations, context);
+                if error.is_none() {
+                    // Nothing else can define a symbol, so forms that read undefined symbols fail.
+                    Scope::finalize(&context.scope());
+                }
+                return error.map(Err);
+            }
+        };
+        let span = element.span;
+
+        if let ir::ElementData::Operation(ref mut op_iter) = element.data {
+            if op_iter.op_name() == USE_DIRECTIVE {
+                if let Err(error) = apply_use_directive(op_iter, &source, operations) {
+                    return Some(Err(error));
+                }
+                continue;
+            }
+        }
+
+        // A form with a parse error is never evaluated, so its calls can't stay pending.
+        let forward_calls = operations.forward_call_count();
+        let result = match element.data {
+            ir::ElementData::Operation(ref mut op_iter) if op_iter.op_name() == DEFOP_FORM => {
+                defop_expression(span, op_iter, &source, operations, context)
+            }
+            _ => expression_from_element(element, &source, operations, context),
+        };
+        if result.is_err() {
+            operations.discard_forward_calls(forward_calls);
+        }
+        // An operation's element span only covers its opening parenthesis.
+        let span = Span::new(span.source(), span.start(), parser.offset() - span.start());
+        return Some(result.map(|e| (span, e)));
     }
 }
 
-pub fn expression_from_element<'a>(
-    element: Element<'a>,
+/**
+ * Returns an error for the next call that was read before its operation was defined, if that
+ * operation still isn't defined at the end of the input
+ *
+ * No later `defop` can define the operation, so the call would stay pending forever. A call to
+ * a name that a symbol holds (such as a function stored with `define_symbol`) is left alone.
+ */
+fn undefined_call_error(
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+) -> Option<ParseError> {
+    while let Some((name, span)) = operations.take_forward_call() {
+        if !context.scope().borrow().is_defined(&name) {
+            return Some(ParseError::new(
+                SourceLocation::from_span(Rc::clone(source), span),
+                ParseErrorCause::UndefinedOperation,
+            ));
+        }
+    }
+    None
+}
+
+/**
+ * Finds the names that `defop` forms and `define_symbol` forms with a literal name define
+ * anywhere in a source
+ *
+ * The source is only split into tokens, so this is cheap, but it also finds forms that fail to
+ * parse or are never evaluated.
+ */
+fn source_definitions(source: &Rc<SourceText>) -> HashSet<Box<str>> {
+    let tokens = ir::Lexer::new(Rc::clone(source))
+        .map_while(Result::ok)
+        .filter(|token| token.token_type != TokenType::Whitespace)
+        .collect::<Vec<_>>();
+    tokens
+        .windows(3)
+        .filter(|window| {
+            window[0].token_type == TokenType::Open && window[1].token_type == TokenType::Symbol
+        })
+        .filter_map(|window| {
+            let operation = source.slice(window[1].span);
+            let name = source.slice(window[2].span);
+            match window[2].token_type {
+                TokenType::Symbol if operation == DEFOP_FORM => Some(name.into()),
+                TokenType::String if operation == &**DEFINE_OP.name() => {
+                    Some(lexer::string_value(name).into())
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/**
+ * Handles a directive of the form `(use "namespace")` or `(use "namespace" "alias")`
+ */
+fn apply_use_directive(
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+) -> Result<(), ParseError> {
+    let mut names: Vec<String> = vec![];
+    while let Some(el_or_err) = op_iter.next_element() {
+        let element = el_or_err?;
+        match element.data {
+            ir::ElementData::String => names.push(string_value(source, element.span)),
+            _ => {
+                return Err(ParseError::new(
+                    SourceLocation::from_span(Rc::clone(source), element.span),
+                    ParseErrorCause::InvalidDirective,
+                ));
+            }
+        }
+    }
+
+    match names.len() {
+        1 => operations.use_namespace(&names[0], None),
+        2 => operations.use_namespace(&names[0], Some(&names[1])),
+        _ => {
+            return Err(ParseError::new(
+                op_iter.location(op_iter.op_span),
+                ParseErrorCause::InvalidDirective,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Checks the operands of an operation against its signature
+ *
+ * `kinds` holds the kinds of the operands that have already been evaluated; the rest are given as
+ * None and are assumed to match.
+ */
+fn check_signature(
+    op: &Operation,
+    source: &Rc<SourceText>,
+    op_span: Span,
+    kinds: &[Option<ValueKind>],
+    operand_spans: &[Span],
+) -> Result<(), ParseError> {
+    match op.signature().check(kinds) {
+        Ok(()) => Ok(()),
+        Err(SignatureMismatch::WrongNumberOfOperands { expected, found }) => Err(ParseError::new(
+            SourceLocation::from_span(Rc::clone(source), op_span),
+            ParseErrorCause::WrongNumberOfOperands { expected, found },
+        )),
+        Err(SignatureMismatch::WrongOperandKind {
+            index,
+            expected,
+            found,
+        }) => Err(ParseError::new(
+            SourceLocation::from_span(Rc::clone(source), operand_spans[index]),
+            ParseErrorCause::WrongOperandType { expected, found },
+        )),
+    }
+}
+
+/**
+ * Looks up the operation that an operation element applies
+ *
+ * Returns None if the operation isn't defined yet but may still be defined by a `defop` form.
+ */
+fn lookup_operation(
+    op_iter: &ir::OperationIterator,
     operations: &OperationGroup,
+) -> Result<Option<Operation>, ParseError> {
+    let name = op_iter.op_name();
+    if name == DEFOP_FORM {
+        return Err(ParseError::new(
+            op_iter.location(op_iter.op_span),
+            ParseErrorCause::InvalidDefop,
+        ));
+    }
+    match operations.get(name) {
+        Some(op) => Ok(Some(op.clone())),
+        None if is_definable(name) => Ok(None),
+        None => Err(ParseError::new(
+            op_iter.location(op_iter.op_span),
+            ParseErrorCause::UndefinedOperation,
+        )),
+    }
+}
+
+/**
+ * Returns true if a `defop` form may define an operation with the given name
+ */
+fn is_definable(name: &str) -> bool {
+    !canonical_name(name).contains(NAMESPACE_SEPARATOR)
+        && name != USE_DIRECTIVE
+        && name != LAMBDA_FORM
+        && name != DEFOP_FORM
+}
+
+/**
+ * Returns the span of a whole operation element once its operands have been read
+ */
+fn form_span(element_span: Span, op_iter: &ir::OperationIterator) -> Span {
+    Span::new(
+        element_span.source(),
+        element_span.start(),
+        op_iter.offset() - element_span.start(),
+    )
+}
+
+/**
+ * Returns the value of an integer literal
+ */
+fn integer_value(source: &SourceText, span: Span) -> Value {
+    // The lexer only produces valid integer literals.
+    Value::parse_integer(source.slice(span)).unwrap()
+}
+
+/**
+ * Returns the value of a string literal
+ */
+fn string_value(source: &SourceText, span: Span) -> String {
+    lexer::string_value(source.slice(span)).into_owned()
+}
+
+/**
+ * An element that has been read but not built
+ *
+ * Operations are looked up and checked against their signatures when the template is read, so
+ * all parse errors are reported up front, even for operands that are never built (such as the
+ * operands of a lazy operation or the body of a function).
+ */
+#[derive(Debug)]
+enum Template {
+    Value(ValueResult),
+    Form {
+        op: Operation,
+        /// The context that the form is evaluated in (see `EvaluationContext::with_form()`)
+        context: EvaluationContext,
+        operands: Vec<Rc<Template>>,
+    },
+    Lambda {
+        parameters: Vec<String>,
+        body: Rc<Template>,
+    },
+}
+
+impl Template {
+    fn kind(&self) -> Option<ValueKind> {
+        match *self {
+            Template::Value(ref value) => expression::Value::kind(value),
+            Template::Form { .. } => None,
+            Template::Lambda { .. } => Some(ValueKind::Function),
+        }
+    }
+
+    /**
+     * Builds the template's expression, evaluating its forms in the given scope
+     */
+    fn build_in(&self, scope: &Rc<RefCell<Scope>>) -> Expression {
+        match *self {
+            Template::Value(ref value) => Expression::from_value(value.clone()),
+            Template::Form {
+                ref op,
+                ref context,
+                ref operands,
+            } => {
+                let context = context.with_scope(Rc::clone(scope));
+                if op.is_lazy() {
+                    let quoted = operands
+                        .iter()
+                        .map(|operand| {
+                            Rc::new(ScopedTemplate {
+                                template: Rc::clone(operand),
+                                scope: Rc::clone(scope),
+                            }) as QuotedOperand
+                        })
+                        .collect();
+                    Expression::from_lazy_op(op.clone(), &context, quoted)
+                } else {
+                    let operands = operands
+                        .iter()
+                        .map(|operand| operand.build_in(scope))
+                        .collect();
+                    Expression::from_op(op.clone(), &context, operands)
+                }
+            }
+            Template::Lambda {
+                ref parameters,
+                ref body,
+            } => {
+                let function = Function::new(
+                    parameters.clone(),
+                    Rc::clone(body) as Rc<dyn FunctionBody>,
+                    Rc::clone(scope),
+                );
+                Expression::from_value(Ok(Value::Function(Rc::new(function))))
+            }
+        }
+    }
+}
+
+impl FunctionBody for Template {
+    fn build(&self, scope: &Rc<RefCell<Scope>>) -> Expression {
+        self.build_in(scope)
+    }
+}
+
+/**
+ * A template that is built in a particular scope
+ */
+#[derive(Debug)]
+struct ScopedTemplate {
+    template: Rc<Template>,
+    scope: Rc<RefCell<Scope>>,
+}
+
+impl Quoted<EvaluationContext> for ScopedTemplate {
+    fn build(&self) -> Expression {
+        self.template.build_in(&self.scope)
+    }
+}
+
+/**
+ * Reads an element into a Template without evaluating anything
+ *
+ * `parameters` holds the names of the parameters of the enclosing `defop` and `lambda` forms,
+ * which may appear as bare symbols.
+ */
+fn template_from_element<'a>(
+    element: Element<'a>,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
     context: &EvaluationContext,
-) -> Result<Expression, ParseError> {
+    parameters: &[String],
+) -> Result<Template, ParseError> {
     match element.data {
         ir::ElementData::Operation(mut op_iter) => {
-            let op = match operations.get(op_iter.op_text.text()) {
-                Some(op) => *op,
-                None => {
-                    return Err(ParseError::new(
-                        op_iter.op_text,
-                        ParseErrorCause::UndefinedOperation,
-                    ));
-                }
-            };
-            let mut operands: Vec<Expression> = vec![];
-
-            while let Some(op_or_err) = op_iter.next_element() {
-                match op_or_err {
-                    Ok(op_el) => match expression_from_element(op_el, operations, context) {
-                        Ok(exp) => operands.push(exp),
-                        Err(err) => return Err(err),
-                    },
-                    Err(err) => return Err(err),
+            if op_iter.op_name() == LAMBDA_FORM {
+                return lambda_template(&mut op_iter, source, operations, context, parameters);
+            }
+            match lookup_operation(&op_iter, operations)? {
+                Some(op) => form_template(
+                    op,
+                    element.span,
+                    &mut op_iter,
+                    source,
+                    operations,
+                    context,
+                    parameters,
+                ),
+                None => call_template(
+                    element.span,
+                    &mut op_iter,
+                    source,
+                    operations,
+                    context,
+                    parameters,
+                ),
+            }
+        }
+        ir::ElementData::Empty => Err(ParseError::new(
+            SourceLocation::from_span(Rc::clone(source), element.span),
+            ParseErrorCause::MissingOperation,
+        )),
+        ir::ElementData::Integer => Ok(Template::Value(Ok(integer_value(source, element.span)))),
+        ir::ElementData::String => Ok(Template::Value(Ok(Value::String(string_value(
+            source,
+            element.span,
+        ))))),
+        ir::ElementData::Symbol => {
+            let name = source.slice(element.span);
+            if !parameters.iter().any(|parameter| parameter == name) {
+                return Err(ParseError::new(
+                    SourceLocation::from_span(Rc::clone(source), element.span),
+                    ParseErrorCause::MisplacedSymbol,
+                ));
+            }
+            Ok(symbol_template(name, element.span, context))
+        }
+    }
+}
+
+/**
+ * Returns a Template that reads a symbol
+ */
+fn symbol_template(name: &str, span: Span, context: &EvaluationContext) -> Template {
+    Template::Form {
+        op: GET_SYM_OP,
+        context: context.with_form(span, GET_SYM_OP.name().clone()),
+        operands: vec![Rc::new(Template::Value(Ok(Value::String(name.to_owned()))))],
+    }
+}
+
+/**
+ * Reads the remaining operands of an operation element into Templates
+ *
+ * Returns the operands along with their spans.
+ */
+fn operand_templates(
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+    parameters: &[String],
+) -> Result<(Vec<Rc<Template>>, Vec<Span>), ParseError> {
+    let mut operands: Vec<Rc<Template>> = vec![];
+    let mut operand_spans: Vec<Span> = vec![];
+    while let Some(op_or_err) = op_iter.next_element() {
+        let op_el = op_or_err?;
+        operand_spans.push(op_el.span);
+        operands.push(Rc::new(template_from_element(
+            op_el, source, operations, context, parameters,
+        )?));
+    }
+    Ok((operands, operand_spans))
+}
+
+/**
+ * Reads the operands of an operation element into a Template
+ */
+fn form_template(
+    op: Operation,
+    element_span: Span,
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+    parameters: &[String],
+) -> Result<Template, ParseError> {
+    let (operands, operand_spans) =
+        operand_templates(op_iter, source, operations, context, parameters)?;
+
+    let kinds = operands
+        .iter()
+        .map(|operand| operand.kind())
+        .collect::<Vec<_>>();
+    check_signature(&op, source, op_iter.op_span, &kinds, &operand_spans)?;
+
+    Ok(Template::Form {
+        context: context.with_form(form_span(element_span, op_iter), op.name().clone()),
+        op,
+        operands,
+    })
+}
+
+/**
+ * Reads a call to an operation that isn't defined yet into a Template
+ *
+ * The call applies the function stored in the symbol with the operation's name, so it waits
+ * for that symbol to be defined (see `DEFOP_FORM`).
+ */
+fn call_template(
+    element_span: Span,
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+    parameters: &[String],
+) -> Result<Template, ParseError> {
+    let name = op_iter.op_name();
+    if !parameters.iter().any(|parameter| parameter == name) {
+        if !context.scope().borrow().is_defined(name)
+            && !operations.may_define(source, name, source_definitions)
+        {
+            return Err(ParseError::new(
+                op_iter.location(op_iter.op_span),
+                ParseErrorCause::UndefinedOperation,
+            ));
+        }
+        operations.add_forward_call(name, op_iter.op_span);
+    }
+    let function = symbol_template(name, op_iter.op_span, context);
+    let (arguments, _) = operand_templates(op_iter, source, operations, context, parameters)?;
+
+    let mut operands = vec![Rc::new(function)];
+    operands.extend(arguments);
+    Ok(Template::Form {
+        op: APPLY_OP,
+        context: context.with_form(form_span(element_span, op_iter), APPLY_OP.name().clone()),
+        operands,
+    })
+}
+
+/**
+ * Reads a parameter list of the form `(parameter ...)` or `()`
+ *
+ * The parameters must be distinct bare symbols. `invalid` builds the error for an element that
+ * isn't a valid parameter list or parameter.
+ */
+fn parameter_list<F: Fn(Span) -> ParseError>(
+    element: Element,
+    source: &Rc<SourceText>,
+    invalid: F,
+) -> Result<Vec<String>, ParseError> {
+    let mut params_iter = match element.data {
+        ir::ElementData::Empty => return Ok(vec![]),
+        ir::ElementData::Operation(params_iter) => params_iter,
+        _ => return Err(invalid(element.span)),
+    };
+
+    // The list reads as an operation whose name is the first parameter.
+    let mut parameters = vec![params_iter.op_name().to_owned()];
+    while let Some(el_or_err) = params_iter.next_element() {
+        let parameter = el_or_err?;
+        let text = source.slice(parameter.span);
+        match parameter.data {
+            ir::ElementData::Symbol if !parameters.iter().any(|p| p == text) => {
+                parameters.push(text.to_owned())
+            }
+            _ => return Err(invalid(parameter.span)),
+        }
+    }
+    Ok(parameters)
+}
+
+/**
+ * Reads a `lambda` form into a Template
+ *
+ * The form must be `(lambda (parameter ...) body)`. In the body, the parameters (and those in
+ * `parameters`) may appear as bare symbols.
+ */
+fn lambda_template(
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+    parameters: &[String],
+) -> Result<Template, ParseError> {
+    let invalid = |span| {
+        ParseError::new(
+            SourceLocation::from_span(Rc::clone(source), span),
+            ParseErrorCause::InvalidLambda,
+        )
+    };
+
+    let own_parameters = match op_iter.next_element() {
+        Some(el_or_err) => parameter_list(el_or_err?, source, invalid)?,
+        None => return Err(invalid(op_iter.op_span)),
+    };
+    let visible = [parameters, &own_parameters[..]].concat();
+    let body = match op_iter.next_element() {
+        Some(el_or_err) => {
+            template_from_element(el_or_err?, source, operations, context, &visible)?
+        }
+        None => return Err(invalid(op_iter.op_span)),
+    };
+    if let Some(el_or_err) = op_iter.next_element() {
+        return Err(invalid(el_or_err?.span));
+    }
+
+    Ok(Template::Lambda {
+        parameters: own_parameters,
+        body: Rc::new(body),
+    })
+}
+
+/**
+ * Reads a `defop` form, defines its symbol and adds its operation to `operations`
+ *
+ * The form must be `(defop name (parameter ...) body)`. If the symbol can't be defined, the
+ * expression fails and no operation is added.
+ */
+fn defop_expression(
+    element_span: Span,
+    op_iter: &mut ir::OperationIterator,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+) -> Result<Expression, ParseError> {
+    let invalid = |span| {
+        ParseError::new(
+            SourceLocation::from_span(Rc::clone(source), span),
+            ParseErrorCause::InvalidDefop,
+        )
+    };
+
+    let name = match op_iter.next_element() {
+        Some(el_or_err) => {
+            let element = el_or_err?;
+            let name = source.slice(element.span);
+            match element.data {
+                ir::ElementData::Symbol if is_definable(name) => {
+                    if operations.get(name).is_some() {
+                        return Err(ParseError::new(
+                            SourceLocation::from_span(Rc::clone(source), element.span),
+                            ParseErrorCause::OperationRedefined,
+                        ));
+                    }
+                    name.to_owned()
                 }
+                _ => return Err(invalid(element.span)),
             }
+        }
+        None => return Err(invalid(op_iter.op_span)),
+    };
+
+    let parameters = match op_iter.next_element() {
+        Some(el_or_err) => parameter_list(el_or_err?, source, invalid)?,
+        None => return Err(invalid(op_iter.op_span)),
+    };
 
-            Ok(Expression::from_op(op, context, operands))
+    let body = match op_iter.next_element() {
+        Some(el_or_err) => {
+            template_from_element(el_or_err?, source, operations, context, &parameters)?
         }
-        ir::ElementData::Integer => {
-            // TODO: handle integer parsing better.
-            let value = str::parse::<usize>(element.location.text()).unwrap();
-            Ok(Expression::from_value(Ok(Value::Integer(value))))
+        None => return Err(invalid(op_iter.op_span)),
+    };
+    if let Some(el_or_err) = op_iter.next_element() {
+        return Err(invalid(el_or_err?.span));
+    }
+
+    let arity = parameters.len();
+    let function = Function::new(parameters, Rc::new(body), context.scope());
+    let value = Value::Function(Rc::new(function));
+    let form_context = context.with_form(form_span(element_span, op_iter), DEFOP_FORM);
+    if let Err(previous) = form_context.define_symbol(&name, Ok(value.clone())) {
+        let error = ValueError::new(ValueErrorCause::SymbolRedefined {
+            name,
+            previous: Box::new(previous.value),
+            first_definition: previous.span,
+        });
+        return Ok(Expression::from_value(Err(
+            error.with_context(&form_context, &[])
+        )));
+    }
+
+    operations.insert(&name, function::defined_operation(name.clone(), arity));
+    Ok(Expression::from_value(Ok(value)))
+}
+
+/**
+ * Builds an expression from a parsed element
+ *
+ * `source` must be the source that the element was parsed from. The whole element is read and
+ * checked before anything is built, so an element with a parse error has no side effects apart
+ * from the calls to undefined operations that it recorded in `operations`.
+ */
+pub fn expression_from_element<'a>(
+    element: Element<'a>,
+    source: &Rc<SourceText>,
+    operations: &mut OperationGroup,
+    context: &EvaluationContext,
+) -> Result<Expression, ParseError> {
+    let template = template_from_element(element, source, operations, context, &[])?;
+    Ok(template.build_in(&context.scope()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::diagnostic::Diagnostic;
+    use base::value::default_operations;
+
+    /**
+     * Reads and evaluates every form in `text`
+     */
+    fn evaluate(text: &str) -> (Rc<SourceText>, Vec<Result<Expression, ParseError>>) {
+        let source = Rc::new(SourceText::new(text.to_owned()).unwrap());
+        let mut parser = ir::Parser::new(ir::Lexer::new(Rc::clone(&source)));
+        let mut operations = default_operations();
+        let context = EvaluationContext::new(Rc::new(RefCell::new(Scope::new())));
+        let mut expressions = vec![];
+        while let Some(result) = expression_from_parser(&mut parser, &mut operations, &context) {
+            expressions.push(result);
         }
-        ir::ElementData::String => {
-            let text = element.location.text();
-            let value = text[1..text.len() - 1].to_owned();
-            Ok(Expression::from_value(Ok(Value::String(value))))
+        (source, expressions)
+    }
+
+    /**
+     * Describes what each form in `text` evaluates to: a value, `error: ...`, `pending` or
+     * `parse error: ...`
+     */
+    fn outcomes(text: &str) -> Vec<String> {
+        evaluate(text)
+            .1
+            .into_iter()
+            .map(|result| match result {
+                Ok(Expression::Total(Ok(value))) => value.to_string(),
+                Ok(Expression::Total(Err(error))) => format!("error: {}", error),
+                Ok(Expression::Partial(_)) => "pending".to_owned(),
+                Err(error) => format!("parse error: {}", error),
+            })
+            .collect()
+    }
+
+    /**
+     * Returns the error that the last form in `text` evaluates to
+     */
+    fn last_error(text: &str) -> (Rc<SourceText>, ValueError) {
+        let (source, mut expressions) = evaluate(text);
+        match expressions.pop() {
+            Some(Ok(Expression::Total(Err(error)))) => (source, error),
+            _ => panic!("{:?} doesn't end with an error", text),
         }
     }
+
+    #[test]
+    fn errors_record_their_form() {
+        let (source, error) = last_error("(add 1 (div 10\n  0))");
+        assert!(matches!(error.cause(), ValueErrorCause::DivisionByZero));
+        assert_eq!(source.slice(error.span().unwrap()), "(div 10\n  0)");
+        assert_eq!(error.operation(), Some("div"));
+        assert_eq!(error.operands(), ["10", "0"]);
+        assert_eq!(error.operand_index(), None);
+        assert_eq!(
+            outcomes("(div 1 0) (add 1 2)"),
+            ["error: division by zero in `div`", "3"]
+        );
+    }
+
+    #[test]
+    fn errors_record_the_wrong_operand() {
+        let long = "x".repeat(40);
+        let text = format!(
+            "(define_symbol \"s\" \"{}\") (add 1 (get_symbol \"s\"))",
+            long
+        );
+        let (_, error) = last_error(&text);
+        assert!(matches!(
+            error.cause(),
+            ValueErrorCause::WrongTypesForOperation
+        ));
+        assert_eq!(error.operation(), Some("add"));
+        assert_eq!(error.operand_index(), Some(1));
+        assert_eq!(
+            error.operands(),
+            ["1".to_owned(), format!("\"{}...", &long[..31])]
+        );
+    }
+
+    #[test]
+    fn sibling_errors_are_aggregated() {
+        let (source, error) = last_error("(list (add 1 (div 1 0)) (rem 2 0) 3)");
+        assert!(
+            matches!(error.cause(), ValueErrorCause::Multiple(ref errors) if errors.len() == 2)
+        );
+        assert_eq!(
+            source.slice(error.span().unwrap()),
+            "(list (add 1 (div 1 0)) (rem 2 0) 3)"
+        );
+        let causes = error
+            .root_causes()
+            .into_iter()
+            .map(|cause| (cause.operation(), source.slice(cause.span().unwrap())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            causes,
+            [(Some("div"), "(div 1 0)"), (Some("rem"), "(rem 2 0)")]
+        );
+
+        let diagnostic = Diagnostic::from(error);
+        assert_eq!(diagnostic.code, Some("R0103"));
+        assert_eq!(diagnostic.labels.len(), 2);
+    }
+
+    #[test]
+    fn nested_aggregates_list_every_root_cause() {
+        let (_, error) = last_error("(list (list (div 1 0) (div 2 0)) (div 3 0))");
+        assert_eq!(error.root_causes().len(), 3);
+    }
+
+    #[test]
+    fn pending_errors_record_their_provenance() {
+        let (source, error) = last_error(
+            "(define_symbol \"p\" (add 1 (get_symbol \"later\")))\n\
+             (define_symbol \"later\" (div 1 0))\n\
+             (get_symbol \"p\")",
+        );
+        let frames = error
+            .provenance()
+            .iter()
+            .map(|frame| {
+                (
+                    frame.to_string(),
+                    frame.span().map(|span| source.slice(span)),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [
+                (
+                    "through symbol `later`".to_owned(),
+                    Some("(define_symbol \"later\" (div 1 0))")
+                ),
+                (
+                    "in `add`".to_owned(),
+                    Some("(add 1 (get_symbol \"later\"))")
+                ),
+                (
+                    "through symbol `p`".to_owned(),
+                    Some("(define_symbol \"p\" (add 1 (get_symbol \"later\")))")
+                ),
+            ]
+        );
+        let backtrace = [
+            "   0: division by zero in `div`",
+            "          at 2:24",
+            "   1: through symbol `later`",
+            "          at 2:1",
+            "   2: in `add`",
+            "          at 1:20",
+            "   3: through symbol `p`",
+            "          at 1:1",
+        ];
+        assert_eq!(error.backtrace(&source), backtrace.join("\n") + "\n");
+    }
+
+    #[test]
+    fn redefinitions_point_at_the_first_definition() {
+        let (source, error) = last_error("(define_symbol \"x\" 1)\n(define_symbol \"x\" 2)");
+        let diagnostic = Diagnostic::from(error);
+        assert_eq!(diagnostic.code, Some("R0104"));
+        assert_eq!(
+            source.slice(diagnostic.span.unwrap()),
+            "(define_symbol \"x\" 2)"
+        );
+        assert_eq!(
+            source.slice(diagnostic.labels[0].span),
+            "(define_symbol \"x\" 1)"
+        );
+        assert_eq!(diagnostic.notes[0], "the previous value was `1`");
+    }
+
+    #[test]
+    fn try_describes_the_outcome_as_a_map() {
+        assert_eq!(
+            outcomes(
+                "(try (add 1 2)) (try (div 1 0)) (or_else (div 1 0) 5) (is_error (div 1 0)) \
+                 (error_message (div 1 0)) (error_message 1)"
+            ),
+            [
+                "{\"ok\": true, \"value\": 3}",
+                "{\"ok\": false, \"error\": \"division by zero in `div`\"}",
+                "5",
+                "true",
+                "\"division by zero in `div`\"",
+                "error: wrong operand types for operation in `error_message`",
+            ]
+        );
+    }
+
+    #[test]
+    fn try_waits_for_pending_operands() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"t\" (try (get_symbol \"later\"))) \
+                 (define_symbol \"later\" (div 1 0)) (get_symbol \"t\")"
+            )[2],
+            "{\"ok\": false, \"error\": \"division by zero in `div`\"}"
+        );
+    }
+
+    #[test]
+    fn undefined_symbols_fail_at_the_end_of_the_input() {
+        let (source, mut expressions) =
+            evaluate("(define_symbol \"value\" 1)\n(add 1 (get_symbol \"valeu\"))");
+        let expression = expressions[1].as_mut().unwrap();
+        expression.try_evaluate();
+        let error = match *expression {
+            Expression::Total(Err(ref error)) => error.clone(),
+            _ => panic!("{:?} didn't fail", expression),
+        };
+        assert!(matches!(
+            error.cause(),
+            ValueErrorCause::UndefinedSymbol { ref name, .. } if name == "valeu"
+        ));
+        assert_eq!(
+            source.slice(error.span().unwrap()),
+            "(get_symbol \"valeu\")"
+        );
+        assert_eq!(error.operation(), Some("get_symbol"));
+        assert_eq!(error.operand_index(), Some(0));
+        assert_eq!(error.provenance()[0].to_string(), "in `add`");
+
+        let diagnostic = Diagnostic::from(error);
+        assert!(diagnostic
+            .notes
+            .contains(&"did you mean `value`?".to_owned()));
+    }
+
+    #[test]
+    fn function_bodies_fail_when_the_input_ends() {
+        let (_, mut expressions) = evaluate(
+            "(define_symbol \"f\" (lambda (x) (add x (get_symbol \"later\"))))\n\
+             (apply (get_symbol \"f\") 1)",
+        );
+        let expression = expressions[1].as_mut().unwrap();
+        expression.try_evaluate();
+        assert!(matches!(
+            *expression,
+            Expression::Total(Err(ref error)) if error.operation() == Some("get_symbol")
+        ));
+    }
+
+    #[test]
+    fn pending_conditions_leave_branches_alone() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"r\" (if (get_symbol \"c\") \"yes\" (get_symbol \"never\"))) \
+                 (define_symbol \"a\" (and (get_symbol \"f\") (get_symbol \"never\"))) \
+                 (define_symbol \"o\" (or (get_symbol \"c\") (div 1 0))) \
+                 (define_symbol \"w\" (when (get_symbol \"f\") (get_symbol \"never\"))) \
+                 (define_symbol \"c\" (true)) (define_symbol \"f\" (false)) \
+                 (get_symbol \"r\") (get_symbol \"a\") (get_symbol \"o\") (get_symbol \"w\")"
+            ),
+            [
+                "pending",
+                "pending",
+                "pending",
+                "pending",
+                "true",
+                "false",
+                "\"yes\"",
+                "false",
+                "true",
+                "error: no condition was met in `when`",
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_branches_are_waited_for() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"r\" (if (false) 1 (add 1 (get_symbol \"x\")))) \
+                 (define_symbol \"x\" 2) (get_symbol \"r\")"
+            )[2],
+            "3"
+        );
+    }
+
+    #[test]
+    fn string_literals_support_escapes() {
+        assert_eq!(
+            outcomes(r#"(str::len "a\"b\\c") (str::find "\\\"" "\"")"#),
+            ["5", "1"]
+        );
+        let source = Rc::new(SourceText::new(r#"(str::len "a\xb")"#.to_owned()).unwrap());
+        let error = ir::Lexer::new(source).find_map(Result::err).unwrap();
+        assert_eq!(error.location.offset(), 12);
+    }
+
+    #[test]
+    fn to_string_reads_back() {
+        let values = [
+            "-99999999999999999999",
+            "(parse_float \"-inf\")",
+            "(parse_float \"0.1\")",
+            "\"say \\\"hi\\\" \\\\ é\"",
+            "(false)",
+            "(list 1 (list) (true))",
+            "(map \"a\" (list \"b\") 2 (parse_float \"1.5\"))",
+        ];
+        for &value in &values {
+            let written = match evaluate(&format!("(to_string {})", value)).1.pop() {
+                Some(Ok(Expression::Total(Ok(Value::String(text))))) => text,
+                _ => panic!("{} has no IR text", value),
+            };
+            assert_eq!(written, value);
+            assert_eq!(outcomes(&written), outcomes(value));
+        }
+    }
+
+    #[test]
+    fn lambdas_are_applied() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"adder\" (lambda (n) (lambda (m) (add n m)))) \
+                 (apply (apply (get_symbol \"adder\") 10) 5) \
+                 (apply (lambda (a b) (list b a)) 1 2) (apply (lambda () \"hi\")) \
+                 (define_symbol \"fact\" \
+                   (lambda (n) (if (le n 1) 1 (mul n (apply (get_symbol \"fact\") (sub n 1)))))) \
+                 (apply (get_symbol \"fact\") 20)"
+            ),
+            [
+                "<function (n)>",
+                "15",
+                "[2, 1]",
+                "\"hi\"",
+                "<function (n)>",
+                "2432902008176640000"
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_checks_arity() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"inc\" (lambda (x) (add x 1))) \
+                 (apply (get_symbol \"inc\") 1 2) (apply (get_symbol \"inc\")) (apply 5) \
+                 (apply (get_symbol \"inc\") \"s\")"
+            ),
+            [
+                "<function (x)>",
+                "error: wrong number of operands (expected 1, found 2) in `apply`",
+                "error: wrong number of operands (expected 1, found 0) in `apply`",
+                "parse error: wrong operand type (expected function, found integer) at 1:111",
+                "error: wrong operand types for operation in `add`",
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lambdas_fail_to_parse() {
+        // Only the first error matters; the rest of the form is read again after it.
+        for &(text, column) in &[
+            ("(lambda)", 2),
+            ("(lambda (x))", 2),
+            ("(lambda (x) 1 2)", 15),
+            ("(lambda (x x) 1)", 12),
+            ("(lambda \"x\" 1)", 9),
+            ("(lambda (x \"y\") 1)", 12),
+        ] {
+            assert_eq!(
+                outcomes(text)[0],
+                format!("parse error: invalid lambda at 1:{}", column)
+            );
+        }
+    }
+
+    #[test]
+    fn defop_defines_operations() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"s\" (square 5)) (defop square (x) (mul x x)) (square 6) \
+                 (get_symbol \"s\") (define_symbol \"i\" (inc 1)) \
+                 (define_symbol \"inc\" (lambda (x) (add x 1))) (get_symbol \"i\") \
+                 (defop fact (n) (if (le n 1) 1 (mul n (fact (sub n 1))))) (fact 10)"
+            ),
+            [
+                "pending",
+                "<function (x)>",
+                "36",
+                "25",
+                "pending",
+                "<function (x)>",
+                "2",
+                "<function (n)>",
+                "3628800",
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_to_operations_that_are_never_defined_fail_when_read() {
+        let undefined = |text: &str| outcomes(text)[0] == "parse error: undefined operation at 1:2";
+        assert!(undefined("(ad 1 2) (defop add2 (x y) (add x y))"));
+        // The name must be written literally.
+        assert!(undefined("(h 1) (define_symbol (str::concat \"h\") 1)"));
+        assert!(!undefined("(h 1) (define_symbol \"h\" (lambda (x) x))"));
+        assert_eq!(
+            outcomes("(defop f (x) (g x))")[0],
+            "parse error: undefined operation at 1:15"
+        );
+    }
+
+    #[test]
+    fn defop_checks_arity() {
+        assert_eq!(
+            outcomes(
+                "(define_symbol \"r\" (f 1 2)) (defop f (x) x) (get_symbol \"r\") (f 1 2) (f)"
+            ),
+            [
+                "pending",
+                "<function (x)>",
+                "error: wrong number of operands (expected 1, found 2) in `apply`",
+                "parse error: wrong number of operands (expected 1, found 2) at 1:63",
+                "parse error: wrong number of operands (expected 1, found 0) at 1:71",
+            ]
+        );
+        // Only the first error matters; the rest of the form is read again after it.
+        for &(text, error) in &[
+            ("(defop)", "invalid defop at 1:2"),
+            ("(defop f)", "invalid defop at 1:2"),
+            ("(defop f (x))", "invalid defop at 1:2"),
+            ("(defop f (x) 1 2)", "invalid defop at 1:16"),
+            ("(defop f (x x) 1)", "invalid defop at 1:13"),
+            ("(defop add (x) x)", "operation is already defined at 1:8"),
+            ("(list (defop f (x) x))", "invalid defop at 1:8"),
+        ] {
+            assert_eq!(outcomes(text)[0], format!("parse error: {}", error));
+        }
+    }
+
+    #[test]
+    fn deep_recursion_fails() {
+        // `MAX_CALL_DEPTH` only fits in a main thread's stack, not a test thread's.
+        let thread = ::std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            outcomes(
+                "(defop count (n) (if (eq n 0) 0 (count (sub n 1)))) \
+                 (count 499) (count 500) (count 100000)",
+            )
+        });
+        assert_eq!(
+            thread.unwrap().join().unwrap()[1..],
+            [
+                "0",
+                "error: more than 500 nested function calls in `apply`",
+                "error: more than 500 nested function calls in `apply`",
+            ]
+        );
+    }
 }
@@ -1,10 +1,14 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
 use std::rc::Rc;
 
-use base::source::{SourceLocation, SourceText};
+use base::diagnostic::Diagnostic;
+use base::source;
+use base::source::{SourceLocation, SourceText, Span};
 use ir::charclass;
+use ir::parser::ParseErrorCause;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenType {
@@ -16,10 +20,10 @@ pub enum TokenType {
     String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Token {
     pub token_type: TokenType,
-    pub location: SourceLocation,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug)]
@@ -39,6 +43,20 @@ impl Error for LexicalError {
     }
 }
 
+impl source::Error for LexicalError {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+}
+
+impl From<LexicalError> for Diagnostic {
+    fn from(error: LexicalError) -> Diagnostic {
+        Diagnostic::error("unexpected token".to_owned())
+            .with_code(ParseErrorCause::Lexical.code())
+            .with_span(error.location.to_span())
+    }
+}
+
 pub struct Lexer {
     source: Rc<SourceText>,
     offset: usize,
@@ -57,22 +75,72 @@ impl Lexer {
         self.offset
     }
 
+    /**
+     * Resolves a span produced by this lexer to a SourceLocation
+     */
+    pub fn location(&self, span: Span) -> SourceLocation {
+        SourceLocation::from_span(Rc::clone(&self.source), span)
+    }
+
+    /**
+     * Returns the text covered by a span produced by this lexer
+     */
+    pub fn text(&self, span: Span) -> &str {
+        self.source.slice(span)
+    }
+
     fn pop_token(&mut self, token_type: TokenType, length: usize) -> Token {
         let start = self.offset;
         self.offset += length;
         Token {
             token_type,
-            location: SourceLocation::new(Rc::clone(&self.source), start, length),
+            span: Span::new(self.source.id(), start, length),
+        }
+    }
+}
+
+/**
+ * Returns the value of a string literal token (which must contain only valid escapes)
+ */
+pub fn string_value(literal: &str) -> Cow<'_, str> {
+    let text = &literal['"'.len_utf8()..literal.len() - '"'.len_utf8()];
+    if !text.contains('\\') {
+        return Cow::Borrowed(text);
+    }
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        value.push(if c == '\\' { chars.next().unwrap() } else { c });
+    }
+    Cow::Owned(value)
+}
+
+/**
+ * Returns the length of the string literal at the start of the text, including both quotes
+ *
+ * A backslash escapes a following double quote or backslash. On failure (an unterminated
+ * literal or any other escape sequence) returns the offset of the problem.
+ */
+fn string_length(text: &str) -> Result<usize, usize> {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => return Ok(offset + '"'.len_utf8()),
+            '\\' => match chars.next() {
+                Some((_, '"')) | Some((_, '\\')) => {}
+                _ => return Err(offset),
+            },
+            _ => {}
         }
     }
+    Err(0)
 }
 
 impl Iterator for Lexer {
     type Item = Result<Token, LexicalError>;
 
     fn next(&mut self) -> Option<Result<Token, LexicalError>> {
-        let source = Rc::clone(&self.source);
-        let remaining = &source.text()[self.offset..];
+        let remaining = &self.source.text()[self.offset..];
 
         if remaining.is_empty() {
             return None;
@@ -88,17 +156,12 @@ impl Iterator for Lexer {
         }
 
         if first_char == '"' {
-            // TODO: support escape sequences
-            let mut chars = remaining.chars();
-            let str_len = chars.position({ |c| c != '"' });
-            return match str_len {
-                Some(len) => Some(Ok(
-                    self.pop_token(TokenType::String, len + '"'.len_utf8() * 2)
-                )),
-                None => Some(Err(LexicalError {
-                    location: SourceLocation::new(Rc::clone(&source), self.offset, 0),
-                })),
-            };
+            return Some(match string_length(remaining) {
+                Ok(len) => Ok(self.pop_token(TokenType::String, len)),
+                Err(offset) => Err(LexicalError {
+                    location: SourceLocation::new(Rc::clone(&self.source), self.offset + offset, 0),
+                }),
+            });
         }
 
         let white_len = charclass::match_length(remaining, charclass::is_whitespace);
@@ -106,26 +169,57 @@ impl Iterator for Lexer {
             return Some(Ok(self.pop_token(TokenType::Whitespace, white_len)));
         }
 
-        let int_length = charclass::match_length(remaining, charclass::is_decimal_digit);
+        // A leading minus sign is part of an integer literal.
+        let sign_length = match remaining.chars().nth(1) {
+            Some(ref c) if first_char == '-' && charclass::is_decimal_digit(c) => '-'.len_utf8(),
+            _ => 0,
+        };
+        let unsigned = &remaining[sign_length..];
+        let hex_length = if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+            charclass::match_length(&unsigned[2..], charclass::is_hex_digit)
+        } else {
+            0
+        };
+        let int_length = if hex_length > 0 {
+            "0x".len() + hex_length
+        } else {
+            charclass::match_length(unsigned, charclass::is_decimal_digit)
+        };
         if int_length > 0 {
-            return Some(Ok(self.pop_token(TokenType::Integer, int_length)));
+            return Some(Ok(
+                self.pop_token(TokenType::Integer, sign_length + int_length)
+            ));
         }
 
         // TODO: just make symbols the "default" token type (if nothing else matches)?
         // (Allows for "non-identifier" symbols)
         if charclass::is_identifier_start(&first_char) {
-            let first_len = first_char.len_utf8();
-            let rest = &remaining[first_len..];
-            let rest_len = charclass::match_length(rest, charclass::is_identifier);
-            return Some(Ok(self.pop_token(TokenType::Symbol, first_len + rest_len)));
+            // A symbol is a sequence of identifiers joined by namespace separators.
+            let mut symbol_len = 0;
+            loop {
+                let segment = &remaining[symbol_len..];
+                let first_len = segment.chars().next().unwrap().len_utf8();
+                symbol_len += first_len
+                    + charclass::match_length(&segment[first_len..], charclass::is_identifier);
+
+                let rest = &remaining[symbol_len..];
+                let sep_len = charclass::namespace_separator_length(rest);
+                match rest[sep_len..].chars().next() {
+                    Some(c) if sep_len > 0 && charclass::is_identifier_start(&c) => {
+                        symbol_len += sep_len
+                    }
+                    _ => break,
+                }
+            }
+            return Some(Ok(self.pop_token(TokenType::Symbol, symbol_len)));
         }
 
         // If we get here, the token is invalid.
         // Stop lexing.
         let err_offset = self.offset;
-        self.offset = source.len();
+        self.offset = self.source.len();
         Some(Err(LexicalError {
-            location: SourceLocation::new(Rc::clone(&source), err_offset, 0),
+            location: SourceLocation::new(Rc::clone(&self.source), err_offset, 0),
         }))
     }
 }
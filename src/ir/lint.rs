@@ -0,0 +1,650 @@
+/*!
+ * Lints: checks for code that is valid but probably wrong
+ *
+ * Lints run over a parsed `Program` before it is evaluated. Each lint has a level that
+ * determines whether its findings are ignored, reported as warnings or reported as errors.
+ */
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::ptr;
+
+use base::context::Scope;
+use base::diagnostic::{Diagnostic, Severity};
+use base::expression::canonical_name;
+use base::source::{SourceText, Span};
+use ir::expression::LAMBDA_FORM;
+use ir::program::{Form, FormData, Program};
+
+/**
+ * Determines how a lint's findings are reported
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    /// The lint doesn't run.
+    Allow,
+    /// Findings are reported as warnings.
+    Warn,
+    /// Findings are reported as errors.
+    Deny,
+}
+
+impl LintLevel {
+    /**
+     * Parses a level from its name (`allow`, `warn` or `deny`)
+     */
+    pub fn from_name(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LintLevel {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        };
+        formatter.write_str(name)
+    }
+}
+
+/**
+ * A single check that runs over a program
+ */
+pub trait Lint {
+    /// The name used to configure the lint (e.g. `unused_symbol`)
+    fn name(&self) -> &'static str;
+    /// The diagnostic code for the lint's findings
+    fn code(&self) -> &'static str;
+    fn default_level(&self) -> LintLevel;
+    fn check(&self, program: &Program, context: &mut LintContext);
+}
+
+/**
+ * The state available to a lint while it runs
+ */
+pub struct LintContext<'a> {
+    scope: &'a Scope,
+    name: &'static str,
+    code: &'static str,
+    level: LintLevel,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> LintContext<'a> {
+    /**
+     * Returns the scope that the program will be evaluated in
+     */
+    pub fn scope(&self) -> &Scope {
+        self.scope
+    }
+
+    /**
+     * Reports a finding at the given span
+     */
+    pub fn report(&mut self, span: Span, message: String) {
+        let diagnostic = self.diagnostic(message).with_span(span);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /**
+     * Builds a diagnostic for a finding without reporting it
+     *
+     * This allows lints to attach labels and notes before passing the result to `emit()`.
+     */
+    pub fn diagnostic(&self, message: String) -> Diagnostic {
+        let severity = match self.level {
+            LintLevel::Deny => Severity::Error,
+            _ => Severity::Warning,
+        };
+        Diagnostic::new(severity, message)
+            .with_code(self.code)
+            .with_note(format!("`{}` is set to `{}`", self.name, self.level))
+    }
+
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+/**
+ * A configurable set of lints
+ */
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+    levels: HashMap<Box<str>, LintLevel>,
+}
+
+impl Linter {
+    /**
+     * Creates a linter with no lints
+     */
+    pub fn new() -> Linter {
+        Linter {
+            lints: vec![],
+            levels: HashMap::new(),
+        }
+    }
+
+    /**
+     * Creates a linter with all of the built-in lints at their default levels
+     */
+    pub fn with_builtin_lints() -> Linter {
+        let mut linter = Linter::new();
+        linter.register(Box::new(UnusedSymbol));
+        linter.register(Box::new(UnreachableForm));
+        linter.register(Box::new(ShadowedSymbol));
+        linter.register(Box::new(UndefinedSymbol));
+        linter
+    }
+
+    pub fn register(&mut self, lint: Box<dyn Lint>) {
+        self.lints.push(lint);
+    }
+
+    /**
+     * Overrides the level of a lint
+     *
+     * Returns false if no lint with the given name has been registered.
+     */
+    pub fn set_level(&mut self, name: &str, level: LintLevel) -> bool {
+        if self.lints.iter().all(|lint| lint.name() != name) {
+            return false;
+        }
+        self.levels.insert(name.into(), level);
+        true
+    }
+
+    pub fn level(&self, lint: &dyn Lint) -> LintLevel {
+        self.levels
+            .get(lint.name())
+            .cloned()
+            .unwrap_or_else(|| lint.default_level())
+    }
+
+    /**
+     * Runs every lint that isn't allowed over a program
+     *
+     * `scope` is the scope that the program will be evaluated in.
+     */
+    pub fn run(&self, program: &Program, scope: &Scope) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for lint in self.lints.iter() {
+            let level = self.level(&**lint);
+            if level == LintLevel::Allow {
+                continue;
+            }
+            let mut context = LintContext {
+                scope,
+                name: lint.name(),
+                code: lint.code(),
+                level,
+                diagnostics: vec![],
+            };
+            lint.check(program, &mut context);
+            diagnostics.append(&mut context.diagnostics);
+        }
+        diagnostics
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::with_builtin_lints()
+    }
+}
+
+/*
+ * Built-in lints
+ *
+ * These only understand symbol names that are given as string literals. Operations are
+ * matched by their full canonical names, so `str.concat` and `str::concat` are the same
+ * operation, but an operation that was renamed by a `use` alias isn't recognized.
+ *
+ * Symbols are matched by name across the whole program, ignoring scopes: a `define_symbol` in
+ * one `lambda` body and a `get_symbol` of the same name in another (or at the top level) count
+ * as a definition and a read of the same symbol, even though the read can't see the definition.
+ */
+
+const DEFINE_SYMBOL: &str = "define_symbol";
+const GET_SYMBOL: &str = "get_symbol";
+
+/**
+ * Returns true if the form applies the operation with the given canonical name
+ */
+fn is_operation(form: &Form, source: &SourceText, op_name: &str) -> bool {
+    form.operation_name(source)
+        .is_some_and(|name| canonical_name(name) == op_name)
+}
+
+/**
+ * A reference to a symbol by a literal name
+ */
+struct SymbolReference<'a> {
+    name: Cow<'a, str>,
+    form: &'a Form,
+}
+
+/**
+ * Finds all forms that apply the given operation to a literal symbol name
+ */
+fn symbol_references<'a>(program: &'a Program, op_name: &str) -> Vec<SymbolReference<'a>> {
+    let mut references = vec![];
+    for form in program.forms() {
+        collect_symbol_references(form, program.source(), op_name, &mut references);
+    }
+    references
+}
+
+fn collect_symbol_references<'a>(
+    form: &'a Form,
+    source: &'a SourceText,
+    op_name: &str,
+    references: &mut Vec<SymbolReference<'a>>,
+) {
+    if is_operation(form, source, op_name) {
+        if let Some(name) = form.operands().first().and_then(|o| o.string_value(source)) {
+            references.push(SymbolReference { name, form });
+        }
+    }
+    for operand in form.operands() {
+        collect_symbol_references(operand, source, op_name, references);
+    }
+}
+
+/**
+ * Finds the parameter names of all `lambda` forms
+ */
+fn lambda_parameters(program: &Program) -> Vec<&str> {
+    let source = program.source();
+    let mut parameters = vec![];
+    program.visit(|form| {
+        if form.operation_name(source) != Some(LAMBDA_FORM) {
+            return;
+        }
+        // The parameter list reads as an operation whose name is the first parameter.
+        if let Some(&Form {
+            data: FormData::Operation { name, ref operands },
+            ..
+        }) = form.operands().first()
+        {
+            parameters.push(source.slice(name));
+            parameters.extend(
+                operands
+                    .iter()
+                    .filter(|operand| matches!(operand.data, FormData::Symbol))
+                    .map(|operand| source.slice(operand.span)),
+            );
+        }
+    });
+    parameters
+}
+
+/**
+ * Warns about symbols that are defined but never read
+ *
+ * A read of the same name anywhere in the program counts, even one in another scope.
+ */
+pub struct UnusedSymbol;
+
+impl Lint for UnusedSymbol {
+    fn name(&self) -> &'static str {
+        "unused_symbol"
+    }
+
+    fn code(&self) -> &'static str {
+        "R0200"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    fn check(&self, program: &Program, context: &mut LintContext) {
+        let reads = symbol_references(program, GET_SYMBOL);
+        for definition in symbol_references(program, DEFINE_SYMBOL) {
+            if reads.iter().all(|read| read.name != definition.name) {
+                context.report(
+                    definition.form.span,
+                    format!("symbol `{}` is never read", definition.name),
+                );
+            }
+        }
+    }
+}
+
+/**
+ * Returns the value of a form if it is a literal boolean (`(true)` or `(false)`)
+ */
+fn literal_boolean(form: &Form, source: &SourceText) -> Option<bool> {
+    if !form.operands().is_empty() {
+        None
+    } else if is_operation(form, source, "true") {
+        Some(true)
+    } else if is_operation(form, source, "false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/**
+ * An operand that a conditional operation can never take
+ */
+struct UnreachableOperand<'a> {
+    operand: &'a Form,
+    /// The literal condition that rules the operand out
+    condition: &'a Form,
+    value: bool,
+}
+
+/**
+ * Finds the operands of a conditional form that are ruled out by literal conditions
+ */
+fn unreachable_operands<'a>(form: &'a Form, source: &SourceText) -> Vec<UnreachableOperand<'a>> {
+    let operands = form.operands();
+    let name = match form.operation_name(source) {
+        Some(name) => canonical_name(name),
+        None => return vec![],
+    };
+    let literal = |index: usize| operands.get(index).and_then(|o| literal_boolean(o, source));
+
+    // Pairs of (unreachable operand, condition that rules it out), by index
+    let mut ruled_out = vec![];
+    match &name[..] {
+        "if" => match literal(0) {
+            Some(true) => ruled_out.extend((2..operands.len()).map(|i| (i, 0))),
+            Some(false) => ruled_out.extend((1..operands.len().min(2)).map(|i| (i, 0))),
+            None => {}
+        },
+        "when" if literal(0) == Some(false) => {
+            ruled_out.extend((1..operands.len()).map(|i| (i, 0)));
+        }
+        "cond" => {
+            for index in (0..operands.len() / 2).map(|pair| pair * 2) {
+                match literal(index) {
+                    Some(true) => {
+                        ruled_out.extend((index + 2..operands.len()).map(|i| (i, index)));
+                        break;
+                    }
+                    Some(false) => ruled_out.push((index + 1, index)),
+                    None => {}
+                }
+            }
+        }
+        "and" | "or" => {
+            // `and` stops at the first false operand, and `or` stops at the first true one.
+            let stop = name == "or";
+            if let Some(index) = (0..operands.len()).find(|&i| literal(i) == Some(stop)) {
+                ruled_out.extend((index + 1..operands.len()).map(|i| (i, index)));
+            }
+        }
+        _ => {}
+    }
+
+    ruled_out
+        .into_iter()
+        .map(|(operand, condition)| UnreachableOperand {
+            operand: &operands[operand],
+            condition: &operands[condition],
+            value: literal(condition) == Some(true),
+        })
+        .collect()
+}
+
+/**
+ * Warns about operands of conditional forms that can never be evaluated
+ *
+ * An operand is unreachable if a literal `(true)` or `(false)` condition means that `if`,
+ * `when`, `cond`, `and` or `or` never takes it. Forms inside an unreachable operand aren't
+ * reported separately.
+ */
+pub struct UnreachableForm;
+
+impl UnreachableForm {
+    fn check_form(&self, form: &Form, source: &SourceText, context: &mut LintContext) {
+        let unreachable = unreachable_operands(form, source);
+        for operand in form.operands() {
+            match unreachable.iter().find(|u| ptr::eq(u.operand, operand)) {
+                Some(unreachable) => {
+                    let diagnostic = context
+                        .diagnostic("form can never be evaluated".to_owned())
+                        .with_span(operand.span)
+                        .with_label(
+                            unreachable.condition.span,
+                            format!("this condition is always {}", unreachable.value),
+                        );
+                    context.emit(diagnostic);
+                }
+                None => self.check_form(operand, source, context),
+            }
+        }
+    }
+}
+
+impl Lint for UnreachableForm {
+    fn name(&self) -> &'static str {
+        "unreachable_form"
+    }
+
+    fn code(&self) -> &'static str {
+        "R0201"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    fn check(&self, program: &Program, context: &mut LintContext) {
+        for form in program.forms() {
+            self.check_form(form, program.source(), context);
+        }
+    }
+}
+
+/**
+ * Warns about definitions that hide a symbol from a parent scope
+ *
+ * Every `define_symbol` in the program is checked against the parent of the scope that the
+ * program is evaluated in, including definitions inside `lambda` bodies, which actually hide
+ * symbols from the call's scope instead.
+ */
+pub struct ShadowedSymbol;
+
+impl Lint for ShadowedSymbol {
+    fn name(&self) -> &'static str {
+        "shadowed_symbol"
+    }
+
+    fn code(&self) -> &'static str {
+        "R0202"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    fn check(&self, program: &Program, context: &mut LintContext) {
+        let parent = match context.scope().parent() {
+            Some(parent) => parent,
+            None => return,
+        };
+        for definition in symbol_references(program, DEFINE_SYMBOL) {
+            if parent.borrow().is_defined(&definition.name) {
+                context.report(
+                    definition.form.span,
+                    format!(
+                        "symbol `{}` shadows a symbol from a parent scope",
+                        definition.name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/**
+ * Warns about symbols that are read but never defined
+ *
+ * Reading a symbol that doesn't exist yet leaves the form pending until the symbol is
+ * defined, so if nothing ever defines the symbol, the form never produces a value. The
+ * parameters of any `lambda` count as definitions.
+ */
+pub struct UndefinedSymbol;
+
+impl Lint for UndefinedSymbol {
+    fn name(&self) -> &'static str {
+        "undefined_symbol"
+    }
+
+    fn code(&self) -> &'static str {
+        "R0203"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    fn check(&self, program: &Program, context: &mut LintContext) {
+        let definitions = symbol_references(program, DEFINE_SYMBOL);
+        let parameters = lambda_parameters(program);
+        for read in symbol_references(program, GET_SYMBOL) {
+            let defined = context.scope().is_defined(&read.name)
+                || definitions.iter().any(|d| d.name == read.name)
+                || parameters.contains(&&*read.name);
+            if !defined {
+                let diagnostic = context
+                    .diagnostic(format!("symbol `{}` is never defined", read.name))
+                    .with_span(read.form.span)
+                    .with_note("the form stays pending until the symbol is defined".to_owned());
+                context.emit(diagnostic);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base::source::SourceText;
+    use base::value::Value;
+    use ir::lexer::Lexer;
+    use ir::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const LEVELS: [LintLevel; 3] = [LintLevel::Allow, LintLevel::Warn, LintLevel::Deny];
+
+    /**
+     * Runs a single lint at the given level and returns the messages and severities it reports
+     */
+    fn run(
+        lint: Box<dyn Lint>,
+        level: LintLevel,
+        text: &str,
+        scope: &Scope,
+    ) -> Vec<(Severity, String)> {
+        let source = Rc::new(SourceText::new(text.to_owned()).unwrap());
+        let program = Program::parse(&mut Parser::new(Lexer::new(source))).unwrap();
+        let mut linter = Linter::new();
+        let name = lint.name();
+        linter.register(lint);
+        assert!(linter.set_level(name, level));
+        linter
+            .run(&program, scope)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+            .collect()
+    }
+
+    /**
+     * Returns the findings that a lint would report at each level
+     */
+    fn run_at_levels(
+        lint: fn() -> Box<dyn Lint>,
+        text: &str,
+        scope: &Scope,
+    ) -> Vec<Vec<(Severity, String)>> {
+        LEVELS
+            .iter()
+            .map(|&level| run(lint(), level, text, scope))
+            .collect()
+    }
+
+    fn findings(message: &str) -> Vec<Vec<(Severity, String)>> {
+        vec![
+            vec![],
+            vec![(Severity::Warning, message.to_owned())],
+            vec![(Severity::Error, message.to_owned())],
+        ]
+    }
+
+    #[test]
+    fn levels_are_parsed() {
+        for level in LEVELS.iter() {
+            assert_eq!(LintLevel::from_name(&level.to_string()), Some(*level));
+        }
+        assert_eq!(LintLevel::from_name("forbid"), None);
+        assert!(!Linter::with_builtin_lints().set_level("forbid", LintLevel::Deny));
+    }
+
+    #[test]
+    fn unused_symbol() {
+        let text = "(define_symbol \"x\" 1) (define_symbol \"y\" 2) (get_symbol \"y\")";
+        assert_eq!(
+            run_at_levels(|| Box::new(UnusedSymbol), text, &Scope::new()),
+            findings("symbol `x` is never read")
+        );
+    }
+
+    #[test]
+    fn unreachable_form() {
+        let text = "(if (false) (add 1 2) 3) (and (get_symbol \"x\") (true))";
+        assert_eq!(
+            run_at_levels(|| Box::new(UnreachableForm), text, &Scope::new()),
+            findings("form can never be evaluated")
+        );
+    }
+
+    #[test]
+    fn shadowed_symbol() {
+        let parent = Rc::new(RefCell::new(Scope::new()));
+        let text = "(define_symbol \"x\" 1)";
+        assert!(run(
+            Box::new(ShadowedSymbol),
+            LintLevel::Deny,
+            text,
+            &Scope::new()
+        )
+        .is_empty());
+        parent
+            .borrow_mut()
+            .define_symbol("x", Ok(Value::Integer(1)), None)
+            .unwrap();
+        assert_eq!(
+            run_at_levels(
+                || Box::new(ShadowedSymbol),
+                text,
+                &Scope::with_parent(&parent)
+            ),
+            findings("symbol `x` shadows a symbol from a parent scope")
+        );
+    }
+
+    #[test]
+    fn undefined_symbol() {
+        let text = "(get_symbol \"x\") (lambda (y) (get_symbol \"y\"))";
+        assert_eq!(
+            run_at_levels(|| Box::new(UndefinedSymbol), text, &Scope::new()),
+            findings("symbol `x` is never defined")
+        );
+    }
+}
@@ -1,7 +1,11 @@
 pub mod charclass;
+pub mod error;
 pub mod expression;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
+pub mod program;
 
+pub use self::error::*;
 pub use self::lexer::*;
 pub use self::parser::*;
@@ -3,25 +3,33 @@ use std::fmt;
 use std::fmt::{Debug, Display};
 use std::rc::Rc;
 
-use base::source::{SourceLocation, SourceText};
+use base::diagnostic::Diagnostic;
+use base::expression::Arity;
+use base::source;
+use base::source::{SourceLocation, SourceText, Span};
+use base::value::ValueKind;
 use ir::lexer::{Lexer, LexicalError, Token, TokenType};
 
 pub struct Element<'a> {
-    pub location: SourceLocation,
+    pub span: Span,
     pub data: ElementData<'a>,
 }
 
 impl<'a> Element<'a> {
-    pub fn new(location: SourceLocation, data: ElementData) -> Element {
-        Element { location, data }
+    pub fn new(span: Span, data: ElementData) -> Element {
+        Element { span, data }
     }
 }
 
 // TODO: parse integers and strings. (Handle in lexer?)
 pub enum ElementData<'a> {
     Operation(OperationIterator<'a>),
+    /// A pair of parentheses with nothing between them, which can only appear as a parameter list
+    Empty,
     Integer,
     String,
+    /// A bare symbol, which can only appear as an operand
+    Symbol,
 }
 
 #[derive(Clone, Debug)]
@@ -32,7 +40,19 @@ pub enum ParseErrorCause {
     MisplacedSymbol,
     MissingOperation,
     UndefinedOperation,
+    WrongNumberOfOperands {
+        expected: Arity,
+        found: usize,
+    },
+    WrongOperandType {
+        expected: &'static [ValueKind],
+        found: ValueKind,
+    },
+    InvalidDirective,
     TrailingText,
+    InvalidLambda,
+    InvalidDefop,
+    OperationRedefined,
 }
 
 #[derive(Clone, Debug)]
@@ -45,11 +65,83 @@ impl ParseError {
     pub fn new(location: SourceLocation, cause: ParseErrorCause) -> ParseError {
         ParseError { location, cause }
     }
+
+    pub fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+
+    pub fn cause(&self) -> &ParseErrorCause {
+        &self.cause
+    }
+}
+
+impl ParseErrorCause {
+    /**
+     * Returns the cause's stable diagnostic code (see `base::explain`)
+     */
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseErrorCause::Lexical => "R0001",
+            ParseErrorCause::UnclosedParen => "R0002",
+            ParseErrorCause::ExtraCloseParen => "R0003",
+            ParseErrorCause::MisplacedSymbol => "R0004",
+            ParseErrorCause::MissingOperation => "R0005",
+            ParseErrorCause::UndefinedOperation => "R0006",
+            ParseErrorCause::WrongNumberOfOperands { .. } => "R0007",
+            ParseErrorCause::WrongOperandType { .. } => "R0008",
+            ParseErrorCause::InvalidDirective => "R0009",
+            ParseErrorCause::TrailingText => "R0010",
+            ParseErrorCause::InvalidLambda => "R0011",
+            ParseErrorCause::InvalidDefop => "R0012",
+            ParseErrorCause::OperationRedefined => "R0013",
+        }
+    }
+}
+
+impl Display for ParseErrorCause {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorCause::Lexical => formatter.write_str("unexpected token"),
+            ParseErrorCause::UnclosedParen => formatter.write_str("unclosed parenthesis"),
+            ParseErrorCause::ExtraCloseParen => {
+                formatter.write_str("unmatched closing parenthesis")
+            }
+            ParseErrorCause::MisplacedSymbol => {
+                formatter.write_str("symbol outside of operation position")
+            }
+            ParseErrorCause::MissingOperation => {
+                formatter.write_str("expected an operation name after `(`")
+            }
+            ParseErrorCause::UndefinedOperation => formatter.write_str("undefined operation"),
+            ParseErrorCause::WrongNumberOfOperands { expected, found } => write!(
+                formatter,
+                "wrong number of operands (expected {}, found {})",
+                expected, found
+            ),
+            ParseErrorCause::WrongOperandType { expected, found } => {
+                formatter.write_str("wrong operand type (expected ")?;
+                for (i, kind) in expected.iter().enumerate() {
+                    if i > 0 {
+                        formatter.write_str(" or ")?;
+                    }
+                    write!(formatter, "{}", kind)?;
+                }
+                write!(formatter, ", found {})", found)
+            }
+            ParseErrorCause::InvalidDirective => formatter.write_str("invalid directive"),
+            ParseErrorCause::TrailingText => formatter.write_str("unexpected trailing text"),
+            ParseErrorCause::InvalidLambda => formatter.write_str("invalid lambda"),
+            ParseErrorCause::InvalidDefop => formatter.write_str("invalid defop"),
+            ParseErrorCause::OperationRedefined => {
+                formatter.write_str("operation is already defined")
+            }
+        }
+    }
 }
 
 impl Display for ParseError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        self.cause.fmt(formatter)
+        write!(formatter, "{} at {}", self.cause, self.location)
     }
 }
 
@@ -59,6 +151,29 @@ impl Error for ParseError {
     }
 }
 
+impl source::Error for ParseError {
+    fn location(&self) -> &SourceLocation {
+        &self.location
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Diagnostic {
+        let message = match error.cause {
+            ParseErrorCause::UndefinedOperation => {
+                format!("undefined operation `{}`", error.location.text())
+            }
+            ParseErrorCause::OperationRedefined => {
+                format!("operation `{}` is already defined", error.location.text())
+            }
+            ref cause => cause.to_string(),
+        };
+        Diagnostic::error(message)
+            .with_code(error.cause.code())
+            .with_span(error.location.to_span())
+    }
+}
+
 /*
  * Helper functions for Parser
  */
@@ -101,7 +216,7 @@ impl Parser {
                 Ok(token) => {
                     if token.token_type != TokenType::Whitespace {
                         return Err(ParseError::new(
-                            token.location,
+                            self.lexer.location(token.span),
                             ParseErrorCause::TrailingText,
                         ));
                     }
@@ -125,20 +240,17 @@ impl Parser {
         match next_token {
             Some(Ok(token)) => Some(match token.token_type {
                 TokenType::Whitespace => panic!("Whitespace should be filtered out"),
-                TokenType::Open => match OperationIterator::new(&mut self.lexer) {
-                    Ok(iter) => Ok(Element::new(token.location, ElementData::Operation(iter))),
-                    Err(error) => Err(error),
-                },
+                TokenType::Open => OperationIterator::open(&mut self.lexer, token.span),
                 TokenType::Close => Err(ParseError::new(
-                    token.location,
+                    self.lexer.location(token.span),
                     ParseErrorCause::ExtraCloseParen,
                 )),
                 TokenType::Symbol => Err(ParseError::new(
-                    token.location,
+                    self.lexer.location(token.span),
                     ParseErrorCause::MisplacedSymbol,
                 )),
-                TokenType::Integer => Ok(Element::new(token.location, ElementData::Integer)),
-                TokenType::String => Ok(Element::new(token.location, ElementData::String)),
+                TokenType::Integer => Ok(Element::new(token.span, ElementData::Integer)),
+                TokenType::String => Ok(Element::new(token.span, ElementData::String)),
             }),
             Some(Err(error)) => Some(Err(ParseError::new(
                 error.location,
@@ -150,31 +262,36 @@ impl Parser {
 }
 
 pub struct OperationIterator<'a> {
-    pub op_text: SourceLocation,
+    pub op_span: Span,
     lexer: &'a mut Lexer,
 }
 
 impl<'a> OperationIterator<'a> {
     /**
      * Given a lexer that has just "seen" the opening parenthesis of an
-     * operation, returns either an OperationIterator or a syntax error
+     * operation, returns either an operation element, an empty element, or a
+     * syntax error
      */
-    fn new(lexer: &'a mut Lexer) -> Result<OperationIterator<'a>, ParseError> {
+    fn open(lexer: &'a mut Lexer, open_span: Span) -> Result<Element<'a>, ParseError> {
         let op_token = next_non_white(lexer);
         match op_token {
-            Some(Ok(op_t)) => {
-                if op_t.token_type == TokenType::Symbol {
-                    Ok(OperationIterator {
-                        op_text: op_t.location,
+            Some(Ok(op_t)) => match op_t.token_type {
+                TokenType::Symbol => Ok(Element::new(
+                    open_span,
+                    ElementData::Operation(OperationIterator {
+                        op_span: op_t.span,
                         lexer,
-                    })
-                } else {
-                    Err(ParseError::new(
-                        op_t.location,
-                        ParseErrorCause::MissingOperation,
-                    ))
-                }
-            }
+                    }),
+                )),
+                TokenType::Close => Ok(Element::new(
+                    Span::join(open_span, op_t.span),
+                    ElementData::Empty,
+                )),
+                _ => Err(ParseError::new(
+                    lexer.location(op_t.span),
+                    ParseErrorCause::MissingOperation,
+                )),
+            },
             Some(Err(error)) => Err(ParseError::new(error.location, ParseErrorCause::Lexical)),
             None => Err(ParseError::new(
                 SourceLocation::new(Rc::clone(lexer.source()), lexer.offset(), 0),
@@ -183,26 +300,40 @@ impl<'a> OperationIterator<'a> {
         }
     }
 
+    /**
+     * Returns the name of the operation
+     */
+    pub fn op_name(&self) -> &str {
+        self.lexer.text(self.op_span)
+    }
+
+    /**
+     * Resolves a span from the operation's source to a SourceLocation
+     */
+    pub fn location(&self, span: Span) -> SourceLocation {
+        self.lexer.location(span)
+    }
+
+    /**
+     * Returns the lexer's current offset
+     *
+     * Once `next_element()` has returned None, this points just past the closing parenthesis.
+     */
+    pub fn offset(&self) -> usize {
+        self.lexer.offset()
+    }
+
     pub fn next_element(&mut self) -> Option<Result<Element, ParseError>> {
         let next_token = next_non_white(self.lexer);
 
         match next_token {
             Some(Ok(token)) => match token.token_type {
                 TokenType::Whitespace => panic!("Whitespace should be filtered out"),
-                TokenType::Open => match OperationIterator::new(self.lexer) {
-                    Ok(iter) => Some(Ok(Element::new(
-                        token.location,
-                        ElementData::Operation(iter),
-                    ))),
-                    Err(error) => Some(Err(error)),
-                },
+                TokenType::Open => Some(OperationIterator::open(self.lexer, token.span)),
                 TokenType::Close => None,
-                TokenType::Symbol => Some(Err(ParseError::new(
-                    token.location,
-                    ParseErrorCause::MisplacedSymbol,
-                ))),
-                TokenType::Integer => Some(Ok(Element::new(token.location, ElementData::Integer))),
-                TokenType::String => Some(Ok(Element::new(token.location, ElementData::String))),
+                TokenType::Symbol => Some(Ok(Element::new(token.span, ElementData::Symbol))),
+                TokenType::Integer => Some(Ok(Element::new(token.span, ElementData::Integer))),
+                TokenType::String => Some(Ok(Element::new(token.span, ElementData::String))),
             },
             Some(Err(error)) => Some(Err(ParseError::new(
                 error.location,
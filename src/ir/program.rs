@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+use std::rc::Rc;
+
+use base::source::{SourceText, Span};
+use ir::lexer;
+use ir::parser::{Element, ElementData, OperationIterator, ParseError, Parser};
+
+/**
+ * A completely parsed element
+ *
+ * Unlike an `Element`, a Form owns its operands, so it can be inspected and traversed freely.
+ */
+#[derive(Clone, Debug)]
+pub struct Form {
+    /// The span of the whole form (including any parentheses)
+    pub span: Span,
+    pub data: FormData,
+}
+
+#[derive(Clone, Debug)]
+pub enum FormData {
+    Operation {
+        name: Span,
+        operands: Vec<Form>,
+    },
+    /// A pair of parentheses with nothing between them
+    Empty,
+    Integer,
+    String,
+    Symbol,
+}
+
+impl Form {
+    /**
+     * Reads the rest of an element into a Form
+     */
+    pub fn from_element(element: Element) -> Result<Form, ParseError> {
+        let span = element.span;
+        match element.data {
+            ElementData::Operation(mut op_iter) => {
+                let name = op_iter.op_span;
+                let operands = operands_from_iterator(&mut op_iter)?;
+                let end = op_iter.offset();
+                Ok(Form {
+                    span: Span::new(span.source(), span.start(), end - span.start()),
+                    data: FormData::Operation { name, operands },
+                })
+            }
+            ElementData::Empty => Ok(Form {
+                span,
+                data: FormData::Empty,
+            }),
+            ElementData::Integer => Ok(Form {
+                span,
+                data: FormData::Integer,
+            }),
+            ElementData::String => Ok(Form {
+                span,
+                data: FormData::String,
+            }),
+            ElementData::Symbol => Ok(Form {
+                span,
+                data: FormData::Symbol,
+            }),
+        }
+    }
+
+    /**
+     * Returns the name of the operation if the form is an operation
+     */
+    pub fn operation_name<'a>(&self, source: &'a SourceText) -> Option<&'a str> {
+        match self.data {
+            FormData::Operation { name, .. } => Some(source.slice(name)),
+            _ => None,
+        }
+    }
+
+    /**
+     * Returns the form's operands (or an empty slice if the form isn't an operation)
+     */
+    pub fn operands(&self) -> &[Form] {
+        match self.data {
+            FormData::Operation { ref operands, .. } => operands,
+            _ => &[],
+        }
+    }
+
+    /**
+     * Returns the value of the form if it is a string literal
+     */
+    pub fn string_value<'a>(&self, source: &'a SourceText) -> Option<Cow<'a, str>> {
+        match self.data {
+            FormData::String => Some(lexer::string_value(source.slice(self.span))),
+            _ => None,
+        }
+    }
+
+    /**
+     * Calls `visitor` on this form and all of its descendants (parents before children)
+     */
+    pub fn visit<F: FnMut(&Form)>(&self, visitor: &mut F) {
+        visitor(self);
+        for operand in self.operands() {
+            operand.visit(visitor);
+        }
+    }
+}
+
+fn operands_from_iterator(op_iter: &mut OperationIterator) -> Result<Vec<Form>, ParseError> {
+    let mut operands = vec![];
+    while let Some(element) = op_iter.next_element() {
+        operands.push(Form::from_element(element?)?);
+    }
+    Ok(operands)
+}
+
+/**
+ * The complete contents of a source, parsed into Forms
+ */
+#[derive(Clone, Debug)]
+pub struct Program {
+    source: Rc<SourceText>,
+    forms: Vec<Form>,
+}
+
+impl Program {
+    /**
+     * Parses all remaining elements from a parser
+     */
+    pub fn parse(parser: &mut Parser) -> Result<Program, ParseError> {
+        let source = Rc::clone(parser.source());
+        let mut forms = vec![];
+        while let Some(element) = parser.next_element() {
+            forms.push(Form::from_element(element?)?);
+        }
+        Ok(Program { source, forms })
+    }
+
+    pub fn source(&self) -> &Rc<SourceText> {
+        &self.source
+    }
+
+    pub fn forms(&self) -> &[Form] {
+        &self.forms
+    }
+
+    /**
+     * Returns the text covered by a span in the program's source
+     */
+    pub fn text(&self, span: Span) -> &str {
+        self.source.slice(span)
+    }
+
+    /**
+     * Calls `visitor` on every form in the program
+     */
+    pub fn visit<F: FnMut(&Form)>(&self, mut visitor: F) {
+        for form in self.forms.iter() {
+            form.visit(&mut visitor);
+        }
+    }
+}